@@ -1,7 +1,7 @@
 use proc_macro2::TokenStream;
 use quote::quote;
 
-#[proc_macro_derive(DynStruct)]
+#[proc_macro_derive(DynStruct, attributes(dyn_struct))]
 pub fn derive_dyn_struct(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = syn::parse_macro_input!(input as syn::DeriveInput);
 
@@ -22,10 +22,15 @@ macro_rules! err {
 fn expand(input: syn::DeriveInput) -> syn::Result<TokenStream> {
     match &input.data {
         syn::Data::Struct(struc) => {
-            check_repr(&input)?;
+            let align = parse_repr(&input)?;
 
             let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
 
+            let options = field_options(struc)?;
+            let struct_options = struct_options(&input)?;
+            let krate = &struct_options.krate;
+            let vis = &struct_options.vis;
+            let dynamic = &struct_options.param;
             let (sized_fields, dynamic_field) = collect_fields(struc)?;
 
             let single = syn::Ident::new(
@@ -33,84 +38,731 @@ fn expand(input: syn::DeriveInput) -> syn::Result<TokenStream> {
                 input.ident.span(),
             );
 
-            let phantom_field;
-            let phantom_init;
-            if input.generics.lt_token.is_some() {
-                let variables = input.generics.params.iter().map(|param| match param {
-                    syn::GenericParam::Type(ty) => {
-                        let ident = &ty.ident;
-                        quote! { #ident }
-                    }
-                    syn::GenericParam::Lifetime(life) => {
-                        let lifetime = &life.lifetime;
-                        quote!{ &#lifetime () }
-                    },
-                    syn::GenericParam::Const(constant) => {
-                        let ident = &constant.ident;
-                        quote!{ [(); #ident] }
-                    },
-                });
+            // The header always ends in a zero-sized field, which also marks where the fields in
+            // front of the slice end (see `TAIL_OFFSET` below).
+            // (type parameters may be `?Sized`, so they are only used behind a pointer)
+            let variables = input.generics.params.iter().map(|param| match param {
+                syn::GenericParam::Type(ty) => {
+                    let ident = &ty.ident;
+                    quote! { *const #ident }
+                }
+                syn::GenericParam::Lifetime(life) => {
+                    let lifetime = &life.lifetime;
+                    quote!{ &#lifetime () }
+                },
+                syn::GenericParam::Const(constant) => {
+                    let ident = &constant.ident;
+                    quote!{ [(); #ident] }
+                },
+            });
 
-                phantom_field = quote! {
-                    __DynStruct_phantom: std::marker::PhantomData<(#(#variables,)*)>
-                };
-                phantom_init = quote! { __DynStruct_phantom: std::marker::PhantomData };
-            } else {
-                phantom_field = quote! {};
-                phantom_init = quote! {};
+            let phantom_type = quote! { ::core::marker::PhantomData<(#(#variables,)*)> };
+
+            // the header has the same alignment as the struct, so that the layout of the value does
+            // as well
+            let repr_align = match &align {
+                Some(align) => quote! { , align(#align) },
+                None => quote! {},
             };
 
             let single_definition;
-            let single_init;
             let single_idents: Vec<syn::Ident>;
+            let phantom_member;
             if matches!(struc.fields, syn::Fields::Named(_)) {
                 single_definition = quote! {
-                    #[repr(C)]
-                    #[derive(Copy, Clone)]
-                    struct #single #impl_generics #where_clause {
+                    #[repr(C #repr_align)]
+                    pub struct #single #impl_generics #where_clause {
                         #(#sized_fields,)*
-                        #phantom_field
+                        __DynStruct_phantom: #phantom_type,
                     }
                 };
                 single_idents = sized_fields
                     .iter()
                     .map(|field| field.ident.clone().unwrap())
                     .collect();
-                single_init = quote! { #single { #(#single_idents,)* #phantom_init } };
+                phantom_member = quote! { __DynStruct_phantom };
             } else {
                 single_definition = quote! {
-                    #[repr(C)]
-                    #[derive(Copy, Clone)]
-                    struct #single #impl_generics ( #(#sized_fields,)* #phantom_init ) #where_clause;
+                    #[repr(C #repr_align)]
+                    pub struct #single #impl_generics ( #(#sized_fields,)* #phantom_type ) #where_clause;
                 };
                 single_idents = sized_fields
                     .iter()
                     .enumerate()
                     .map(|(i, field)| syn::Ident::new(&format!("_{}", i), span(field)))
                     .collect();
-                single_init = quote! { #single ( #(#single_idents,)*, #phantom_init ) };
+                let index = syn::Index::from(sized_fields.len());
+                phantom_member = quote! { #index };
             };
 
-            let sized_parameters = sized_fields.iter().enumerate().map(|(i, field)| {
-                let name = &single_idents[i];
-                let ty = &field.ty;
-                quote! { #name: #ty }
-            });
+            // the field which stores the number of elements, which is not passed to the
+            // constructors, but set from the length of the array
+            let len_field = match &options.len {
+                Some(len) => {
+                    let position = single_idents.iter().position(|ident| ident == len);
+                    let position = position.ok_or_else(|| err!(len, "no field named `{}`", len))?;
+                    Some((position, len, &sized_fields[position].ty))
+                }
+                None => None,
+            };
+
+            // the variables holding the value of each field of the header, where the length field
+            // gets a name of its own, so it cannot shadow any of the parameters
+            let len_variable = syn::Ident::new("__dyn_struct_len_field", proc_macro2::Span::call_site());
+            let single_variables = single_idents
+                .iter()
+                .enumerate()
+                .map(|(i, ident)| match len_field {
+                    Some((position, ..)) if position == i => &len_variable,
+                    _ => ident,
+                })
+                .collect::<Vec<_>>();
+            let single_init = if matches!(struc.fields, syn::Fields::Named(_)) {
+                quote! {
+                    #single {
+                        #(#single_idents: #single_variables,)*
+                        __DynStruct_phantom: ::core::marker::PhantomData,
+                    }
+                }
+            } else {
+                quote! { #single ( #(#single_variables,)* ::core::marker::PhantomData ) }
+            };
+
+            let parameters = sized_fields
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !matches!(len_field, Some((position, ..)) if position == *i))
+                .map(|(i, field)| (&single_idents[i], &field.ty))
+                .collect::<Vec<_>>();
+            let sized_parameters = parameters
+                .iter()
+                .map(|(name, ty)| quote! { #name: #ty })
+                .collect::<Vec<_>>();
+            let parameter_idents = parameters.iter().map(|(name, _)| name).collect::<Vec<_>>();
+            let parameter_types = parameters.iter().map(|(_, ty)| ty).collect::<Vec<_>>();
 
             let ident = &input.ident;
             let dynamic_type = &dynamic_field.ty;
-            Ok(quote! {
-                impl #impl_generics #ident #type_generics #where_clause {
-                    pub fn new(#(#sized_parameters,)* dynamic: &#dynamic_type) -> Box<Self> {
-                        #single_definition
+            // Bounds on concrete types which do not hold (such as `String: Copy`) are errors, even
+            // if the function is never called. Making them higher-ranked defers the check to the
+            // call site, so they are written as `for<'__dyn_struct> #element_type: Clone`.
+            let element_type = slice_element(&dynamic_field)?;
 
+            let sized_types = sized_fields.iter().map(|field| &field.ty).collect::<Vec<_>>();
+
+            // initializes the length field from the number of elements (if there is one)
+            let init_len = |len: TokenStream| match len_field {
+                Some((_, _, ty)) => quote! {
+                    let #len_variable = <#ty as ::core::convert::TryFrom<usize>>::try_from(#len)
+                        .unwrap_or_else(|_| panic!("{}", #krate::DynStructError::LengthOverflow));
+                },
+                None => quote! {},
+            };
+            let init_len_from_dynamic = init_len(quote! { #dynamic.len() });
+            let try_init_len = match len_field {
+                Some((_, _, ty)) => quote! {
+                    let #len_variable = <#ty as ::core::convert::TryFrom<usize>>::try_from(#dynamic.len())
+                        .map_err(|_| #krate::DynStructError::LengthOverflow)?;
+                },
+                None => quote! {},
+            };
+
+            let zeroed_init = match len_field {
+                Some((_, ident, _)) => {
+                    let init_len = init_len(quote! { len });
+                    quote! {{
+                        #init_len
+                        #single { #ident: #len_variable, ..unsafe { ::core::mem::zeroed() } }
+                    }}
+                }
+                None => quote! { unsafe { ::core::mem::zeroed() } },
+            };
+
+            // builds the value from an iterator with the given constructor, or with its `_with`
+            // variant if the header depends on the number of elements
+            let from_iter = |constructor: TokenStream, constructor_with: TokenStream| match len_field {
+                // the header is written once all elements are, and their number is known
+                Some(_) => {
+                    let init_len = init_len(quote! { __dyn_struct_len });
+                    quote! {
+                        #krate::__private::#constructor_with::<Self, _>(#dynamic, |__dyn_struct_len| {
+                            #init_len
+                            #single_init
+                        })
+                    }
+                }
+                None => quote! {
+                    let single: #single #type_generics = #single_init;
+
+                    #krate::__private::#constructor::<Self, _>(single, #dynamic)
+                },
+            };
+            let init_len_zeroed = init_len(quote! { __dyn_struct_len });
+            let from_iter_exact_body =
+                from_iter(quote! { from_iter_exact }, quote! { from_iter_exact_with });
+            let from_iter_body = from_iter(quote! { from_iter }, quote! { from_iter_with });
+
+            let from_bytes_prefix = match len_field {
+                Some((_, ident, _)) => quote! {
+                    #vis fn from_bytes_prefix(
+                        bytes: &[u8],
+                    ) -> ::core::result::Result<(&Self, &[u8]), #krate::DynStructError>
+                    where
+                        #(for<'__dyn_struct> #sized_types: #krate::FromBytes,)*
+                        for<'__dyn_struct> #element_type: #krate::FromBytes,
+                    {
+                        // every field is `FromBytes`, so any bytes are a valid value
+                        unsafe {
+                            #krate::__private::from_bytes_with_len::<Self>(bytes, |single| {
+                                <usize as ::core::convert::TryFrom<_>>::try_from(single.#ident)
+                                    .map_err(|_| #krate::DynStructError::LengthOverflow)
+                            })
+                        }
+                    }
+                },
+                None => quote! {},
+            };
+
+            let dynamic_ident = match &dynamic_field.ident {
+                Some(ident) => quote! { #ident },
+                None => {
+                    let index = syn::Index::from(sized_fields.len());
+                    quote! { #index }
+                }
+            };
+
+            // the header is viewed as bytes as a whole, so there may not be any padding between
+            // its fields
+            let assert_no_padding = quote! {
+                assert!(
+                    ::core::mem::size_of::<#single #type_generics>() == 0 #(+ ::core::mem::size_of::<#sized_types>())*,
+                    "`{}` contains padding bytes",
+                    ::core::stringify!(#ident),
+                );
+            };
+
+            let align = quote! {
+                #krate::__private::max_align(
+                    ::core::mem::align_of::<#single #type_generics>(),
+                    ::core::mem::align_of::<#element_type>(),
+                )
+            };
+
+            let io = if cfg!(feature = "std") {
+                // a value read back has to agree with its length field
+                let check_len = match len_field {
+                    Some((_, len, _)) => quote! {
+                        if <usize as ::core::convert::TryFrom<_>>::try_from(value.#len).ok() != Some(value.#dynamic_ident.len()) {
+                            return Err(#krate::__private::io::Error::new(
+                                #krate::__private::io::ErrorKind::InvalidData,
+                                #krate::DynStructError::LengthOverflow,
+                            ));
+                        }
+                    },
+                    None => quote! {},
+                };
+
+                quote! {
+                    #vis fn write_to<__DynStructWrite>(
+                        &self,
+                        writer: __DynStructWrite,
+                    ) -> #krate::__private::io::Result<()>
+                    where
+                        __DynStructWrite: #krate::__private::io::Write,
+                        #(for<'__dyn_struct> #sized_types: #krate::FromBytes,)*
+                        for<'__dyn_struct> #element_type: #krate::FromBytes,
+                    {
+                        #assert_no_padding
+                        // every field is `FromBytes`, and there is no padding between them
+                        unsafe { #krate::__private::write_to(self, writer) }
+                    }
+
+                    #vis fn read_from<__DynStructRead>(
+                        reader: __DynStructRead,
+                    ) -> #krate::__private::io::Result<#krate::__private::Box<Self>>
+                    where
+                        __DynStructRead: #krate::__private::io::Read,
+                        #(for<'__dyn_struct> #sized_types: #krate::FromBytes,)*
+                        for<'__dyn_struct> #element_type: #krate::FromBytes,
+                    {
+                        // every field is `FromBytes`, so any bytes are a valid value
+                        let value = unsafe { #krate::__private::read_from::<Self, _>(reader)? };
+                        #check_len
+                        Ok(value)
+                    }
+                }
+            } else {
+                quote! {}
+            };
+
+            let new_in = if cfg!(feature = "allocator-api2") {
+                quote! {
+                    #vis fn new_in<__DynStructAlloc>(
+                        #(#sized_parameters,)*
+                        #dynamic: &#dynamic_type,
+                        __dyn_struct_alloc: __DynStructAlloc,
+                    ) -> #krate::allocator_api2::boxed::Box<Self, __DynStructAlloc>
+                    where
+                        for<'__dyn_struct> #element_type: Clone,
+                        __DynStructAlloc: #krate::allocator_api2::alloc::Allocator,
+                    {
+                        #init_len_from_dynamic
                         let single: #single #type_generics = #single_init;
 
-                        let dyn_struct = dyn_struct::DynStruct::new(single, dynamic);
-                        let ptr = std::boxed::Box::into_raw(dyn_struct);
-                        unsafe { std::boxed::Box::from_raw(ptr as *mut Self) }
+                        #krate::__private::new_in::<Self, _>(single, #dynamic, __dyn_struct_alloc)
                     }
                 }
+            } else {
+                quote! {}
+            };
+            let predicates = where_clause
+                .iter()
+                .flat_map(|where_clause| where_clause.predicates.iter())
+                .collect::<Vec<_>>();
+            let single_members = (0..sized_fields.len())
+                .map(|i| match &sized_fields[i].ident {
+                    Some(ident) => quote! { #ident },
+                    None => {
+                        let index = syn::Index::from(i);
+                        quote! { #index }
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            // lets generic owners such as `ThinBox` clone the header
+            let header_clone = quote! {
+                impl #impl_generics ::core::clone::Clone for #single #type_generics
+                where
+                    #(#predicates,)*
+                    #(for<'__dyn_struct> #sized_types: ::core::clone::Clone,)*
+                {
+                    fn clone(&self) -> Self {
+                        #(let #single_variables = ::core::clone::Clone::clone(&self.#single_members);)*
+                        #single_init
+                    }
+                }
+            };
+
+            // clones the value into a new allocation
+            let clone_body = quote! {
+                #(let #single_variables = ::core::clone::Clone::clone(&self.#single_members);)*
+                let single: #single #type_generics = #single_init;
+
+                #krate::__private::new::<#ident #type_generics>(single, &self.#dynamic_ident)
+            };
+            let clone_predicates = quote! {
+                #(#predicates,)*
+                #(for<'__dyn_struct> #sized_types: ::core::clone::Clone,)*
+                for<'__dyn_struct> #element_type: ::core::clone::Clone,
+            };
+
+            let clone = if struct_options.clone {
+                quote! {
+                    impl #impl_generics ::core::clone::Clone for #krate::__private::Box<#ident #type_generics>
+                    where
+                        #clone_predicates
+                    {
+                        fn clone(&self) -> Self {
+                            #clone_body
+                        }
+                    }
+                }
+            } else {
+                quote! {}
+            };
+
+            let to_owned = if struct_options.to_owned {
+                quote! {
+                    impl #impl_generics #krate::__private::ToOwned for #ident #type_generics
+                    where
+                        #clone_predicates
+                    {
+                        type Owned = #krate::__private::Box<Self>;
+
+                        fn to_owned(&self) -> #krate::__private::Box<Self> {
+                            #clone_body
+                        }
+                    }
+                }
+            } else {
+                quote! {}
+            };
+
+            let default = if struct_options.default {
+                let init_len = init_len(quote! { 0 });
+                quote! {
+                    impl #impl_generics ::core::default::Default for #krate::__private::Box<#ident #type_generics>
+                    where
+                        #(#predicates,)*
+                        #(for<'__dyn_struct> #parameter_types: ::core::default::Default,)*
+                    {
+                        fn default() -> Self {
+                            #(let #parameter_idents = ::core::default::Default::default();)*
+                            #init_len
+                            let single: #single #type_generics = #single_init;
+
+                            #krate::__private::from_vec::<#ident #type_generics>(
+                                single,
+                                #krate::__private::Vec::new(),
+                            )
+                        }
+                    }
+                }
+            } else {
+                quote! {}
+            };
+
+            let collection = if struct_options.collection {
+                let mut index_generics = input.generics.clone();
+                index_generics.params.push(syn::parse_quote! { __DynStructIndex });
+                let (index_impl_generics, _, _) = index_generics.split_for_impl();
+
+                let mut iter_generics = input.generics.clone();
+                iter_generics.params.insert(0, syn::parse_quote! { '__dyn_struct });
+                let (iter_impl_generics, _, _) = iter_generics.split_for_impl();
+
+                quote! {
+                    impl #impl_generics #ident #type_generics #where_clause {
+                        #vis fn len(&self) -> usize {
+                            self.#dynamic_ident.len()
+                        }
+
+                        #vis fn is_empty(&self) -> bool {
+                            self.#dynamic_ident.is_empty()
+                        }
+                    }
+
+                    impl #index_impl_generics ::core::ops::Index<__DynStructIndex> for #ident #type_generics
+                    where
+                        #(#predicates,)*
+                        __DynStructIndex: ::core::slice::SliceIndex<[#element_type]>,
+                    {
+                        type Output = __DynStructIndex::Output;
+
+                        fn index(&self, index: __DynStructIndex) -> &Self::Output {
+                            &self.#dynamic_ident[index]
+                        }
+                    }
+
+                    impl #index_impl_generics ::core::ops::IndexMut<__DynStructIndex> for #ident #type_generics
+                    where
+                        #(#predicates,)*
+                        __DynStructIndex: ::core::slice::SliceIndex<[#element_type]>,
+                    {
+                        fn index_mut(&mut self, index: __DynStructIndex) -> &mut Self::Output {
+                            &mut self.#dynamic_ident[index]
+                        }
+                    }
+
+                    impl #iter_impl_generics ::core::iter::IntoIterator for &'__dyn_struct #ident #type_generics #where_clause {
+                        type Item = &'__dyn_struct #element_type;
+                        type IntoIter = ::core::slice::Iter<'__dyn_struct, #element_type>;
+
+                        fn into_iter(self) -> Self::IntoIter {
+                            self.#dynamic_ident.iter()
+                        }
+                    }
+
+                    impl #iter_impl_generics ::core::iter::IntoIterator for &'__dyn_struct mut #ident #type_generics #where_clause {
+                        type Item = &'__dyn_struct mut #element_type;
+                        type IntoIter = ::core::slice::IterMut<'__dyn_struct, #element_type>;
+
+                        fn into_iter(self) -> Self::IntoIter {
+                            self.#dynamic_ident.iter_mut()
+                        }
+                    }
+
+                    impl #impl_generics ::core::convert::AsRef<#dynamic_type> for #ident #type_generics #where_clause {
+                        fn as_ref(&self) -> &#dynamic_type {
+                            &self.#dynamic_ident
+                        }
+                    }
+
+                    impl #impl_generics ::core::convert::AsMut<#dynamic_type> for #ident #type_generics #where_clause {
+                        fn as_mut(&mut self) -> &mut #dynamic_type {
+                            &mut self.#dynamic_ident
+                        }
+                    }
+                }
+            } else {
+                quote! {}
+            };
+
+            let deref = if struct_options.deref {
+                quote! {
+                    impl #impl_generics ::core::ops::Deref for #ident #type_generics #where_clause {
+                        type Target = #dynamic_type;
+
+                        fn deref(&self) -> &#dynamic_type {
+                            &self.#dynamic_ident
+                        }
+                    }
+
+                    impl #impl_generics ::core::ops::DerefMut for #ident #type_generics #where_clause {
+                        fn deref_mut(&mut self) -> &mut #dynamic_type {
+                            &mut self.#dynamic_ident
+                        }
+                    }
+                }
+            } else {
+                quote! {}
+            };
+
+            let constructor = match &struct_options.new {
+                Some(new) => quote! {
+                    #vis fn #new(#(#sized_parameters,)* #dynamic: &#dynamic_type) -> #krate::__private::Box<Self>
+                    where
+                        for<'__dyn_struct> #element_type: Clone,
+                    {
+                        #init_len_from_dynamic
+                        let single: #single #type_generics = #single_init;
+
+                        #krate::__private::new::<Self>(single, #dynamic)
+                    }
+                },
+                None => quote! {},
+            };
+
+            // the offsets are checked once the constant is evaluated, which for generic structs
+            // only happens when they are used
+            let check_layout = if input.generics.params.is_empty() {
+                quote! {
+                    const _: usize = <#ident as #krate::__private::RawDst>::TAIL_OFFSET;
+                }
+            } else {
+                quote! {}
+            };
+
+            Ok(quote! {
+                const _: () = {
+                    #single_definition
+
+                    #header_clone
+
+                    impl #impl_generics #ident #type_generics #where_clause {
+                        #constructor
+
+                        #vis fn try_new(
+                            #(#sized_parameters,)*
+                            #dynamic: &#dynamic_type,
+                        ) -> ::core::result::Result<#krate::__private::Box<Self>, #krate::DynStructError>
+                        where
+                            for<'__dyn_struct> #element_type: Clone,
+                        {
+                            #try_init_len
+                            let single: #single #type_generics = #single_init;
+
+                            #krate::__private::try_new::<Self>(single, #dynamic)
+                        }
+
+                        #vis fn new_zeroed(len: usize) -> #krate::__private::Box<Self>
+                        where
+                            #(for<'__dyn_struct> #sized_types: #krate::Zeroable,)*
+                            for<'__dyn_struct> #element_type: #krate::Zeroable,
+                        {
+                            // every field of the header is `Zeroable`
+                            let single: #single #type_generics = #zeroed_init;
+
+                            // the elements are `Zeroable`
+                            unsafe { #krate::__private::new_zeroed_with_header::<Self>(single, len) }
+                        }
+
+                        #vis fn new_zeroed_with_header(#(#sized_parameters,)* __dyn_struct_len: usize) -> #krate::__private::Box<Self>
+                        where
+                            for<'__dyn_struct> #element_type: #krate::Zeroable,
+                        {
+                            #init_len_zeroed
+                            let single: #single #type_generics = #single_init;
+
+                            // the elements are `Zeroable`
+                            unsafe { #krate::__private::new_zeroed_with_header::<Self>(single, __dyn_struct_len) }
+                        }
+
+                        #vis fn new_from_vec(
+                            #(#sized_parameters,)*
+                            #dynamic: #krate::__private::Vec<#element_type>,
+                        ) -> #krate::__private::Box<Self> {
+                            #init_len_from_dynamic
+                            let single: #single #type_generics = #single_init;
+
+                            #krate::__private::from_vec::<Self>(single, #dynamic)
+                        }
+
+                        #vis fn new_from_iter_exact<__DynStructIter>(
+                            #(#sized_parameters,)*
+                            #dynamic: __DynStructIter,
+                        ) -> #krate::__private::Box<Self>
+                        where
+                            __DynStructIter: ::core::iter::IntoIterator<Item = #element_type>,
+                            __DynStructIter::IntoIter: ::core::iter::ExactSizeIterator,
+                        {
+                            #from_iter_exact_body
+                        }
+
+                        #vis fn new_from_iter<__DynStructIter>(
+                            #(#sized_parameters,)*
+                            #dynamic: __DynStructIter,
+                        ) -> #krate::__private::Box<Self>
+                        where
+                            __DynStructIter: ::core::iter::IntoIterator<Item = #element_type>,
+                        {
+                            #from_iter_body
+                        }
+
+                        #vis fn new_rc(#(#sized_parameters,)* #dynamic: &#dynamic_type) -> #krate::__private::Rc<Self>
+                        where
+                            for<'__dyn_struct> #element_type: Clone,
+                        {
+                            #init_len_from_dynamic
+                            let single: #single #type_generics = #single_init;
+
+                            #krate::__private::new_rc::<Self>(single, #dynamic)
+                        }
+
+                        #[cfg(target_has_atomic = "ptr")]
+                        #vis fn new_arc(#(#sized_parameters,)* #dynamic: &#dynamic_type) -> #krate::__private::Arc<Self>
+                        where
+                            for<'__dyn_struct> #element_type: Clone,
+                        {
+                            #init_len_from_dynamic
+                            let single: #single #type_generics = #single_init;
+
+                            #krate::__private::new_arc::<Self>(single, #dynamic)
+                        }
+
+                        #vis fn new_thin(
+                            #(#sized_parameters,)*
+                            #dynamic: &#dynamic_type,
+                        ) -> #krate::ThinBox<Self>
+                        where
+                            for<'__dyn_struct> #element_type: Clone,
+                        {
+                            #init_len_from_dynamic
+                            let single: #single #type_generics = #single_init;
+
+                            #krate::__private::new_thin::<Self>(single, #dynamic)
+                        }
+
+                        #[cfg(target_has_atomic = "ptr")]
+                        #vis fn new_thin_arc(
+                            #(#sized_parameters,)*
+                            #dynamic: &#dynamic_type,
+                        ) -> #krate::ThinArc<Self>
+                        where
+                            for<'__dyn_struct> #element_type: Clone,
+                        {
+                            #init_len_from_dynamic
+                            let single: #single #type_generics = #single_init;
+
+                            #krate::__private::new_thin_arc::<Self>(single, #dynamic)
+                        }
+
+                        #vis const fn layout_for(
+                            len: usize,
+                        ) -> ::core::result::Result<::core::alloc::Layout, #krate::DynStructError> {
+                            #krate::__private::layout_for(
+                                <Self as #krate::__private::RawDst>::TAIL_OFFSET,
+                                #align,
+                                ::core::mem::size_of::<#element_type>(),
+                                len,
+                            )
+                        }
+
+                        #vis const fn tail_offset() -> usize {
+                            <Self as #krate::__private::RawDst>::TAIL_OFFSET
+                        }
+
+                        #vis const fn max_len() -> usize {
+                            #krate::__private::max_len(
+                                <Self as #krate::__private::RawDst>::TAIL_OFFSET,
+                                #align,
+                                ::core::mem::size_of::<#element_type>(),
+                            )
+                        }
+
+                        #from_bytes_prefix
+
+                        #vis fn as_bytes(&self) -> &[u8]
+                        where
+                            #(for<'__dyn_struct> #sized_types: #krate::FromBytes,)*
+                            for<'__dyn_struct> #element_type: #krate::FromBytes,
+                        {
+                            #assert_no_padding
+                            // every field is `FromBytes`, and there is no padding between them
+                            unsafe { #krate::__private::as_bytes(self) }
+                        }
+
+                        #io
+
+                        #new_in
+
+                        #vis fn into_raw_parts(this: #krate::__private::Box<Self>) -> (*mut u8, usize) {
+                            #krate::__private::into_raw_parts(this)
+                        }
+
+                        #vis unsafe fn from_raw_parts(data: *mut u8, len: usize) -> #krate::__private::Box<Self> {
+                            #krate::__private::from_raw_parts(data, len)
+                        }
+
+                        #vis unsafe fn from_raw_parts_ref<'__dyn_struct>(data: *const u8, len: usize) -> &'__dyn_struct Self {
+                            &*Self::ptr_from_raw_parts(data as *mut u8, len)
+                        }
+
+                        #vis unsafe fn from_raw_parts_mut<'__dyn_struct>(data: *mut u8, len: usize) -> &'__dyn_struct mut Self {
+                            &mut *Self::ptr_from_raw_parts(data, len)
+                        }
+
+                        #vis const fn ptr_from_raw_parts(data: *mut u8, len: usize) -> *mut Self {
+                            ::core::ptr::slice_from_raw_parts_mut(data as *mut (), len) as *mut Self
+                        }
+                    }
+
+                    // the header has the same fields in the same order, and `#[repr(C)]` places them at
+                    // the same offsets
+                    unsafe impl #impl_generics #krate::__private::RawDst for #ident #type_generics #where_clause {
+                        type Header = #single #type_generics;
+                        type Element = #element_type;
+                        const TAIL_OFFSET: usize = {
+                            #(
+                                ::core::assert!(
+                                    ::core::mem::offset_of!(#single #type_generics, #single_members)
+                                        == ::core::mem::offset_of!(#ident #type_generics, #single_members),
+                                    ::core::concat!(
+                                        "the header of `",
+                                        ::core::stringify!(#ident),
+                                        "` has a different layout",
+                                    ),
+                                );
+                            )*
+                            #krate::__private::tail_offset(
+                                ::core::mem::offset_of!(#single #type_generics, #phantom_member),
+                                ::core::mem::align_of::<#element_type>(),
+                            )
+                        };
+                    }
+
+                    #check_layout
+
+                    #clone
+
+                    #to_owned
+
+                    #default
+
+                    #collection
+
+                    #deref
+
+                    unsafe impl #impl_generics #krate::SliceDst for #ident #type_generics #where_clause {
+                        fn ptr_from_raw_parts(data: *mut u8, len: usize) -> *mut Self {
+                            Self::ptr_from_raw_parts(data, len)
+                        }
+
+                        fn ptr_len(ptr: *const Self) -> usize {
+                            (ptr as *const [()]).len()
+                        }
+                    }
+                };
             })
         }
         _ => Err(err!(
@@ -144,54 +796,364 @@ fn collect_fields(struc: &syn::DataStruct) -> syn::Result<(Vec<syn::Field>, syn:
     Ok((fields.into_iter().collect(), dynamic.into_value()))
 }
 
-fn check_repr(input: &syn::DeriveInput) -> syn::Result<()> {
-    if input.attrs.iter().any(|attr| is_repr_c(attr)) {
-        Ok(())
-    } else {
-        Err(err!(
-            &input.ident,
-            "`DynStruct` can only be derived for structs with `#[repr(C)]`"
-        ))
+/// The options given to `#[dyn_struct(...)]` on the fields of the struct.
+#[derive(Default)]
+struct FieldOptions {
+    /// The field which stores the number of elements: `#[dyn_struct(len = field)]`.
+    len: Option<syn::Ident>,
+}
+
+/// The options given to `#[dyn_struct(...)]` on the struct itself.
+struct StructOptions {
+    /// The path of the `dyn_struct` crate: `#[dyn_struct(crate = "path")]`.
+    krate: syn::Path,
+    /// The name of the `new` constructor (`#[dyn_struct(new = name)]`), or `None` if it is
+    /// skipped (`#[dyn_struct(skip_new)]`).
+    new: Option<syn::Ident>,
+    /// The visibility of the generated functions: `#[dyn_struct(vis = "pub(crate)")]`.
+    vis: syn::Visibility,
+    /// The name of the parameter which takes the elements: `#[dyn_struct(param = name)]`.
+    param: syn::Ident,
+    /// Implement `Clone` for `Box<Self>`: `#[dyn_struct(clone)]`.
+    clone: bool,
+    /// Implement `Default` for `Box<Self>`, with no elements: `#[dyn_struct(default)]`.
+    default: bool,
+    /// Implement `ToOwned` with `Box<Self>` as the owned type: `#[dyn_struct(to_owned)]`.
+    to_owned: bool,
+    /// Implement `Index`, `IntoIterator` and `AsRef` over the elements, and add `len` and
+    /// `is_empty`: `#[dyn_struct(collection)]`.
+    collection: bool,
+    /// Implement `Deref` with the slice of elements as the target: `#[dyn_struct(deref)]`.
+    deref: bool,
+}
+
+fn struct_options(input: &syn::DeriveInput) -> syn::Result<StructOptions> {
+    let mut options = StructOptions {
+        krate: syn::parse_quote! { dyn_struct },
+        new: Some(syn::Ident::new("new", proc_macro2::Span::call_site())),
+        vis: syn::parse_quote! { pub },
+        param: syn::Ident::new("dynamic", proc_macro2::Span::call_site()),
+        clone: false,
+        default: false,
+        to_owned: false,
+        collection: false,
+        deref: false,
+    };
+
+    for attr in input.attrs.iter().filter(|attr| attr.path.is_ident("dyn_struct")) {
+        let parser = syn::punctuated::Punctuated::<StructOption, syn::Token![,]>::parse_terminated;
+        for option in attr.parse_args_with(parser)? {
+            match option {
+                StructOption::Clone => options.clone = true,
+                StructOption::Default => options.default = true,
+                StructOption::ToOwned => options.to_owned = true,
+                StructOption::Collection => options.collection = true,
+                StructOption::Deref => options.deref = true,
+                StructOption::Crate(krate) => options.krate = krate,
+                StructOption::New(new) => options.new = Some(new),
+                StructOption::SkipNew => options.new = None,
+                StructOption::Vis(vis) => options.vis = vis,
+                StructOption::Param(param) => options.param = param,
+            }
+        }
+    }
+
+    Ok(options)
+}
+
+enum StructOption {
+    Clone,
+    Default,
+    ToOwned,
+    Collection,
+    Deref,
+    Crate(syn::Path),
+    New(syn::Ident),
+    SkipNew,
+    Vis(syn::Visibility),
+    Param(syn::Ident),
+}
+
+impl syn::parse::Parse for StructOption {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        // `crate` is a keyword, so it is not accepted as a regular identifier
+        let key = input.call(<syn::Ident as syn::ext::IdentExt>::parse_any)?;
+        if key == "crate" {
+            input.parse::<syn::Token![=]>()?;
+            Ok(StructOption::Crate(input.parse::<syn::LitStr>()?.parse()?))
+        } else if key == "new" {
+            input.parse::<syn::Token![=]>()?;
+            Ok(StructOption::New(input.parse()?))
+        } else if key == "skip_new" {
+            Ok(StructOption::SkipNew)
+        } else if key == "vis" {
+            input.parse::<syn::Token![=]>()?;
+            Ok(StructOption::Vis(input.parse::<syn::LitStr>()?.parse()?))
+        } else if key == "param" {
+            input.parse::<syn::Token![=]>()?;
+            Ok(StructOption::Param(input.parse()?))
+        } else if key == "clone" {
+            Ok(StructOption::Clone)
+        } else if key == "default" {
+            Ok(StructOption::Default)
+        } else if key == "to_owned" {
+            Ok(StructOption::ToOwned)
+        } else if key == "collection" {
+            Ok(StructOption::Collection)
+        } else if key == "deref" {
+            Ok(StructOption::Deref)
+        } else {
+            Err(err!(&key, "unknown option `{}`", key))
+        }
+    }
+}
+
+fn field_options(struc: &syn::DataStruct) -> syn::Result<FieldOptions> {
+    let mut options = FieldOptions::default();
+
+    let field_count = struc.fields.len();
+    for (i, field) in struc.fields.iter().enumerate() {
+        for attr in field.attrs.iter().filter(|attr| attr.path.is_ident("dyn_struct")) {
+            if i + 1 != field_count {
+                return Err(err!(
+                    attr,
+                    "`#[dyn_struct(...)]` can only be applied to the last field"
+                ));
+            }
+            if field.ident.is_none() {
+                return Err(err!(
+                    attr,
+                    "`#[dyn_struct(...)]` is only supported on structs with named fields"
+                ));
+            }
+
+            let parser = syn::punctuated::Punctuated::<FieldOption, syn::Token![,]>::parse_terminated;
+            for option in attr.parse_args_with(parser)? {
+                match option {
+                    FieldOption::Len(ident) => options.len = Some(ident),
+                }
+            }
+        }
     }
+
+    Ok(options)
+}
+
+enum FieldOption {
+    Len(syn::Ident),
 }
 
-fn is_repr_c(attr: &syn::Attribute) -> bool {
-    match attr.path.get_ident() {
-        Some(ident) if ident == "repr" => {}
-        _ => return false,
+impl syn::parse::Parse for FieldOption {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let key: syn::Ident = input.parse()?;
+        if key == "len" {
+            input.parse::<syn::Token![=]>()?;
+            Ok(FieldOption::Len(input.parse()?))
+        } else {
+            Err(err!(&key, "unknown option `{}`", key))
+        }
     }
+}
 
-    match find_ident(attr.tokens.clone()) {
-        Some(ident) if ident == "C" => true,
-        _ => false,
+fn slice_element(field: &syn::Field) -> syn::Result<&syn::Type> {
+    match &field.ty {
+        syn::Type::Slice(slice) => Ok(&slice.elem),
+        ty => Err(err!(
+            ty,
+            "the last field of a `DynStruct` has to be a slice (`[T]`)"
+        )),
     }
 }
 
-fn find_ident(tokens: TokenStream) -> Option<syn::Ident> {
-    tokens.into_iter().find_map(|tree| match tree {
-        quote::__private::TokenTree::Group(group) => find_ident(group.stream()),
-        quote::__private::TokenTree::Ident(ident) => Some(ident.clone()),
-        _ => None,
-    })
+/// Checks the `#[repr(...)]` attributes of the struct, which has to be `#[repr(C)]`, and returns
+/// the alignment given by `align(N)`, if any.
+fn parse_repr(input: &syn::DeriveInput) -> syn::Result<Option<syn::LitInt>> {
+    let mut repr_c = false;
+    let mut align = None;
+
+    for attr in input.attrs.iter().filter(|attr| attr.path.is_ident("repr")) {
+        let parser = syn::punctuated::Punctuated::<syn::NestedMeta, syn::Token![,]>::parse_terminated;
+        for nested in attr.parse_args_with(parser)? {
+            let meta = match &nested {
+                syn::NestedMeta::Meta(meta) => meta,
+                syn::NestedMeta::Lit(lit) => return Err(err!(lit, "unsupported representation")),
+            };
+
+            if meta.path().is_ident("packed") {
+                return Err(err!(
+                    meta,
+                    "`DynStruct` cannot be derived for structs with `#[repr(packed)]`, since their fields may be misaligned"
+                ));
+            }
+            if meta.path().is_ident("transparent") {
+                return Err(err!(
+                    meta,
+                    "`DynStruct` cannot be derived for structs with `#[repr(transparent)]`, use `#[repr(C)]` instead"
+                ));
+            }
+
+            match meta {
+                syn::Meta::Path(path) if path.is_ident("C") => repr_c = true,
+                syn::Meta::List(list) if list.path.is_ident("align") => {
+                    match list.nested.iter().collect::<Vec<_>>().as_slice() {
+                        [syn::NestedMeta::Lit(syn::Lit::Int(lit))] => align = Some(lit.clone()),
+                        _ => return Err(err!(list, "expected an alignment, such as `align(8)`")),
+                    }
+                }
+                _ => {
+                    return Err(err!(
+                        meta,
+                        "unsupported representation `{}`",
+                        quote! { #meta }
+                    ))
+                }
+            }
+        }
+    }
+
+    if repr_c {
+        Ok(align)
+    } else {
+        Err(err!(
+            &input.ident,
+            "`DynStruct` can only be derived for structs with `#[repr(C)]`"
+        ))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn repr_error(input: TokenStream) -> String {
+        let input = syn::parse2::<syn::DeriveInput>(input).unwrap();
+        parse_repr(&input).unwrap_err().to_string()
+    }
+
+    fn expand_str(input: TokenStream) -> String {
+        let input = syn::parse2::<syn::DeriveInput>(input).unwrap();
+        expand(input).unwrap().to_string()
+    }
+
+    fn assert_contains(output: &str, expected: TokenStream) {
+        let expected = expected.to_string();
+        assert!(output.contains(&expected), "`{}` not in `{}`", expected, output);
+    }
+
     #[test]
-    fn simple() {
+    fn repr() {
+        let input = quote! {
+            #[repr(align(8), C)]
+            struct Foo {
+                list: [u32],
+            }
+        };
+        let input = syn::parse2::<syn::DeriveInput>(input).unwrap();
+        assert_eq!(parse_repr(&input).unwrap().unwrap().base10_digits(), "8");
+
         let input = quote! {
+            #[repr(C)]
+            #[repr(align(4))]
+            struct Foo {
+                list: [u32],
+            }
+        };
+        let input = syn::parse2::<syn::DeriveInput>(input).unwrap();
+        assert!(parse_repr(&input).unwrap().is_some());
+
+        assert!(repr_error(quote! { #[repr(C, packed)] struct Foo { list: [u8] } })
+            .contains("`#[repr(packed)]`"));
+        assert!(repr_error(quote! { #[repr(C, packed(2))] struct Foo { list: [u8] } })
+            .contains("`#[repr(packed)]`"));
+        assert!(repr_error(quote! { #[repr(transparent)] struct Foo { list: [u8] } })
+            .contains("`#[repr(transparent)]`"));
+        assert!(repr_error(quote! { #[repr(align(8))] struct Foo { list: [u8] } })
+            .contains("`#[repr(C)]`"));
+        assert!(repr_error(quote! { #[repr(Rust)] struct Foo { list: [u8] } })
+            .contains("unsupported representation"));
+        assert!(repr_error(quote! { #[repr(align)] struct Foo { list: [u8] } })
+            .contains("unsupported representation"));
+        assert!(repr_error(quote! { #[repr(align(x))] struct Foo { list: [u8] } })
+            .contains("expected an alignment"));
+        assert!(repr_error(quote! { /** C */ struct Foo { list: [u8] } }).contains("`#[repr(C)]`"));
+    }
+
+    #[test]
+    fn simple() {
+        let output = expand_str(quote! {
             #[repr(C)]
             struct Foo<T, U> {
                 value: u32,
                 list: [u32],
             }
-        }
-        .into();
-        let input = syn::parse2::<syn::DeriveInput>(input).unwrap();
-        let output = expand(input).unwrap();
+        });
+
+        assert_contains(
+            &output,
+            quote! {
+                #[repr(C)]
+                pub struct Foo_DynStruct_Single<T, U> {
+                    value: u32,
+                    __DynStruct_phantom: ::core::marker::PhantomData<(*const T, *const U,)>,
+                }
+            },
+        );
+        assert_contains(&output, quote! { impl<T, U> Foo<T, U> });
+        assert_contains(&output, quote! { pub fn new(value: u32, dynamic: &[u32]) });
+    }
+
+    #[test]
+    fn tuple() {
+        let output = expand_str(quote! {
+            #[repr(C)]
+            struct Row(pub u32, bool, [u8]);
+        });
+
+        assert_contains(
+            &output,
+            quote! {
+                #[repr(C)]
+                pub struct Row_DynStruct_Single(u32, bool, ::core::marker::PhantomData<()>);
+            },
+        );
+        assert_contains(
+            &output,
+            quote! { Row_DynStruct_Single(_0, _1, ::core::marker::PhantomData) },
+        );
+        assert_contains(&output, quote! { pub fn new(_0: u32, _1: bool, dynamic: &[u8]) });
+        assert_contains(&output, quote! { offset_of!(Row_DynStruct_Single, 2) });
+    }
+
+    #[test]
+    fn generic_defaults() {
+        let output = expand_str(quote! {
+            #[repr(C)]
+            struct Foo<'a, T: ?Sized = str, const N: usize = 2>
+            where
+                T: Send,
+            {
+                header: [&'a T; N],
+                list: [u8],
+            }
+        });
 
-        assert_eq!(output.to_string(), quote! {}.to_string());
+        assert_contains(
+            &output,
+            quote! {
+                #[repr(C)]
+                pub struct Foo_DynStruct_Single<'a, T: ?Sized, const N: usize>
+                where
+                    T: Send,
+                {
+                    header: [&'a T; N],
+                    __DynStruct_phantom:
+                        ::core::marker::PhantomData<(&'a (), *const T, [(); N],)>,
+                }
+            },
+        );
+        assert_contains(
+            &output,
+            quote! { impl<'a, T: ?Sized, const N: usize> Foo<'a, T, N> where T: Send, },
+        );
     }
 }
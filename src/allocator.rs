@@ -0,0 +1,57 @@
+use crate::raw::{self, RawDst};
+use crate::DynStruct;
+
+use allocator_api2::alloc::Allocator;
+use allocator_api2::boxed::Box;
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+impl<T, D> DynStruct<T, D> {
+    /// Same as [`DynStruct::new`], but the value is allocated with `alloc` instead of the global
+    /// allocator.
+    pub fn new_in<A>(single: T, many: &[D], alloc: A) -> Box<Self, A>
+    where
+        D: Clone,
+        A: Allocator,
+    {
+        new_in(single, many, alloc)
+    }
+}
+
+/// Same as [`DynStruct::new_in`], but for any `RawDst`.
+pub fn new_in<S, A>(header: S::Header, many: &[S::Element], alloc: A) -> Box<S, A>
+where
+    S: ?Sized + RawDst,
+    S::Element: Clone,
+    A: Allocator,
+{
+    let layout = raw::layout::<S>(many.len());
+    let raw = match alloc.allocate(layout) {
+        Ok(raw) => raw.cast::<u8>(),
+        Err(_) => alloc::alloc::handle_alloc_error(layout),
+    };
+
+    let allocation = AllocationIn {
+        alloc: &alloc,
+        raw,
+        layout,
+    };
+    unsafe {
+        raw::init_with::<S>(raw.as_ptr(), header, many.len(), |i| many[i].clone());
+        core::mem::forget(allocation);
+        Box::from_raw_in(raw::fat_ptr::<S>(raw.as_ptr(), many.len()), alloc)
+    }
+}
+
+/// Memory allocated with `alloc`, which is freed again if dropped.
+struct AllocationIn<'a, A: Allocator> {
+    alloc: &'a A,
+    raw: NonNull<u8>,
+    layout: Layout,
+}
+
+impl<A: Allocator> Drop for AllocationIn<'_, A> {
+    fn drop(&mut self) {
+        unsafe { self.alloc.deallocate(self.raw, self.layout) }
+    }
+}
@@ -0,0 +1,57 @@
+use core::alloc::Layout;
+use core::fmt;
+
+/// The error returned when a `DynStruct` could not be constructed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DynStructError {
+    /// The size of the value does not fit in an `isize`.
+    LayoutOverflow,
+    /// The alignment of the value is not a power of two.
+    InvalidAlignment,
+    /// The allocator could not provide memory for the given layout.
+    AllocError(Layout),
+    /// The buffer is smaller than the given layout.
+    BufferTooSmall(Layout),
+    /// The buffer is not aligned to the alignment of the given layout.
+    MisalignedBuffer(Layout),
+    /// The buffer has the given size, which is not the size of a value with a whole number of
+    /// elements.
+    SizeMismatch(usize),
+    /// The number of elements does not fit in the field which stores it.
+    LengthOverflow,
+}
+
+impl fmt::Display for DynStructError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DynStructError::LayoutOverflow => write!(f, "the size of the `DynStruct` overflowed"),
+            DynStructError::InvalidAlignment => {
+                write!(f, "the alignment of the `DynStruct` is not a power of two")
+            }
+            DynStructError::AllocError(layout) => write!(
+                f,
+                "failed to allocate {} bytes with an alignment of {}",
+                layout.size(),
+                layout.align()
+            ),
+            DynStructError::BufferTooSmall(layout) => {
+                write!(f, "the buffer is too small to fit {} bytes", layout.size())
+            }
+            DynStructError::MisalignedBuffer(layout) => {
+                write!(f, "the buffer is not aligned to {} bytes", layout.align())
+            }
+            DynStructError::SizeMismatch(size) => write!(
+                f,
+                "a buffer of {} bytes does not fit a whole number of elements",
+                size
+            ),
+            DynStructError::LengthOverflow => {
+                write!(f, "the number of elements does not fit in the length field")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DynStructError {}
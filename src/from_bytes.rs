@@ -0,0 +1,39 @@
+use crate::Zeroable;
+
+use core::marker::PhantomData;
+use core::num::{
+    NonZeroI128, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize, NonZeroU128,
+    NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU8, NonZeroUsize, Wrapping,
+};
+
+/// Types which can be read from, and written to, arbitrary bytes.
+///
+/// This is required by [`DynStruct::from_bytes`](crate::DynStruct::from_bytes), which views a
+/// byte slice as a `DynStruct` without copying it.
+///
+/// # Safety
+///
+/// Every bit pattern has to be a valid value of the type, and the type must not contain any
+/// padding bytes, which would be left uninitialized when a value is written into the bytes. This
+/// is the case for integers and floats, but not for `bool`, `char` or most tuples, for example.
+pub unsafe trait FromBytes: Zeroable {}
+
+macro_rules! impl_from_bytes {
+    ($($ty:ty),* $(,)?) => {
+        $( unsafe impl FromBytes for $ty {} )*
+    };
+}
+
+impl_from_bytes! {
+    (), f32, f64,
+    u8, u16, u32, u64, u128, usize,
+    i8, i16, i32, i64, i128, isize,
+    Option<NonZeroU8>, Option<NonZeroU16>, Option<NonZeroU32>,
+    Option<NonZeroU64>, Option<NonZeroU128>, Option<NonZeroUsize>,
+    Option<NonZeroI8>, Option<NonZeroI16>, Option<NonZeroI32>,
+    Option<NonZeroI64>, Option<NonZeroI128>, Option<NonZeroIsize>,
+}
+
+unsafe impl<T: ?Sized> FromBytes for PhantomData<T> {}
+unsafe impl<T: FromBytes> FromBytes for Wrapping<T> {}
+unsafe impl<T: FromBytes, const N: usize> FromBytes for [T; N] {}
@@ -0,0 +1,84 @@
+use crate::raw::{self, RawDst};
+use crate::{Allocation, DynStruct, DynStructError, FromBytes};
+
+use alloc::boxed::Box;
+use core::convert::TryFrom;
+use std::io::{self, Read, Write};
+
+impl<T, D> DynStruct<T, D> {
+    /// Write the value to `writer`, preceded by its number of elements as a little-endian `u64`.
+    /// The value is written in its in-memory representation, with any padding bytes set to zero,
+    /// so it can be read back with [`DynStruct::read_from`] on the same platform.
+    pub fn write_to<W: Write>(&self, writer: W) -> io::Result<()>
+    where
+        T: FromBytes,
+        D: FromBytes,
+    {
+        unsafe { write_to(self, writer) }
+    }
+
+    /// Read a value written by [`DynStruct::write_to`] from `reader`.
+    ///
+    /// Note that the memory for the value is allocated before it is read, so a corrupted number
+    /// of elements may lead to a large allocation.
+    pub fn read_from<R: Read>(reader: R) -> io::Result<Box<Self>>
+    where
+        T: FromBytes,
+        D: FromBytes,
+    {
+        unsafe { read_from(reader) }
+    }
+}
+
+/// Write `value` to `writer`, preceded by its number of elements.
+///
+/// # Safety
+///
+/// `S::Header` and `S::Element` must not contain any padding bytes.
+pub unsafe fn write_to<S: ?Sized + RawDst, W: Write>(value: &S, mut writer: W) -> io::Result<()> {
+    let len = S::ptr_len(value);
+    let raw = value as *const S as *const u8;
+    let header = core::slice::from_raw_parts(raw, core::mem::size_of::<S::Header>());
+    let many = core::slice::from_raw_parts(
+        raw.add(S::TAIL_OFFSET),
+        core::mem::size_of::<S::Element>() * len,
+    );
+    let trailing = core::mem::size_of_val(value) - S::TAIL_OFFSET - many.len();
+
+    writer.write_all(&(len as u64).to_le_bytes())?;
+    writer.write_all(header)?;
+    write_zeros(&mut writer, S::TAIL_OFFSET - header.len())?;
+    writer.write_all(many)?;
+    write_zeros(&mut writer, trailing)
+}
+
+/// Read a value written by [`write_to`] from `reader`.
+///
+/// # Safety
+///
+/// Every bit pattern has to be a valid value of `S::Header` and `S::Element`.
+pub unsafe fn read_from<S: ?Sized + RawDst, R: Read>(mut reader: R) -> io::Result<Box<S>> {
+    let mut len = [0; 8];
+    reader.read_exact(&mut len)?;
+    let len = usize::try_from(u64::from_le_bytes(len))
+        .map_err(|_| invalid_data(DynStructError::LayoutOverflow))?;
+
+    let layout = raw::try_layout::<S>(len).map_err(invalid_data)?;
+    let allocation = Allocation::allocate(layout, alloc::alloc::alloc_zeroed)
+        .map_err(|error| io::Error::new(io::ErrorKind::OutOfMemory, error))?;
+
+    // the memory is zeroed, so it is fine to view it as bytes
+    reader.read_exact(core::slice::from_raw_parts_mut(
+        allocation.raw,
+        layout.size(),
+    ))?;
+    Ok(Box::from_raw(raw::fat_ptr(allocation.into_raw(), len)))
+}
+
+fn write_zeros<W: Write>(writer: &mut W, count: usize) -> io::Result<()> {
+    io::copy(&mut io::repeat(0).take(count as u64), writer).map(drop)
+}
+
+fn invalid_data(error: DynStructError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
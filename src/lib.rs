@@ -50,8 +50,13 @@
 //! ## The Derive Macro
 //! 
 //! The `DynStruct` macro can be applied to any `#[repr(C)]` struct that contains a
-//! dynamically sized array as its last field. Fields only have a single constraint:
-//! they have to implement `Copy`.
+//! dynamically sized array as its last field. The other fields are passed to the
+//! constructors by value, and may have any type. The elements of the array are
+//! cloned from a slice by `new`, or moved out of a `Vec` by `new_from_vec`.
+//! The struct may also be over-aligned with `#[repr(C, align(N))]`, but
+//! `#[repr(packed)]` and `#[repr(transparent)]` are not supported.
+//! Tuple structs, lifetimes, type and const generics (with defaults and
+//! `where` clauses) are supported as well.
 //! 
 //! ### Example
 //! 
@@ -65,7 +70,7 @@
 //! }
 //! ```
 //! 
-//! will produce a single `impl`-block with a `new` function:
+//! will produce a single `impl`-block with a `new` function (among others):
 //! 
 //! ```ignore
 //! impl MyDynamicType {
@@ -76,14 +81,119 @@
 //! ```
 //! 
 //! Due to the nature of dynamically sized types, the resulting value has to be
-//! built on the heap. For safety reasons we currently only allow returning `Box`,
-//! though in a future version we may also allow `Rc` and `Arc`. In the meantime it
-//! is posible to use `Arc::from(MyDynamicType::new(...))`.
+//! built on the heap. Next to `new`, which returns a `Box`, the macro also generates
+//! `new_rc` and `new_arc`, which build the value directly inside the allocation of
+//! an `Rc` or `Arc` respectively, and `new_thin` and `new_thin_arc`, which return a
+//! `ThinBox` or `ThinArc`: pointers which store the length of the array in their
+//! allocation, and thus are only one word wide.
+//! 
+//! If another field stores the number of elements, mark the array with
+//! `#[dyn_struct(len = field)]`. The constructors then set that field from the
+//! length of the array, instead of taking it as a parameter, and the macro also
+//! generates `from_bytes_prefix`, which views the start of a byte slice as the
+//! struct, reading the number of elements from that field.
+//! 
+//! Boxed values implement `Clone`, `Default` (with an empty array) and `ToOwned`
+//! (for use with `Cow`), and `DynStruct` can be indexed and iterated like its
+//! array. For types using the derive macro, these are opt-in through
+//! `#[dyn_struct(clone, default, to_owned, collection)]` on the struct, and
+//! `#[dyn_struct(deref)]` lets the struct dereference to its array.
+//! 
+//! The generated code can be adjusted with more options on the struct:
+//! 
+//! - `new = name` renames the `new` constructor, and `skip_new` leaves it out.
+//! - `vis = "pub(crate)"` sets the visibility of the generated functions (which are
+//!   `pub` by default).
+//! - `param = name` renames the parameter which takes the array (`dynamic` by
+//!   default).
+//! - `crate = "path"` is the path of this crate, if it is not available as
+//!   `dyn_struct`, for example because it is re-exported by another crate.
+//! 
+//! 
+//! ## Cargo Features
+//! 
+//! - `std` (enabled by default): implements `std::error::Error` for `DynStructError`,
+//!   and adds `write_to` and `read_from`, which save and load values through
+//!   `std::io` (also on types using the derive macro). Without it the crate is
+//!   `no_std`, and only depends on `core` and `alloc`.
+//! - `derive` (enabled by default): the `DynStruct` derive macro.
+//! - `allocator-api2`: `DynStruct::new_in` (and a matching `new_in` from the derive
+//!   macro), which allocates the value with a custom allocator through the
+//!   [`allocator-api2`](https://crates.io/crates/allocator-api2) crate. Enable its
+//!   `nightly` feature to use the allocators of the standard library instead.
 
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
 
 #[cfg(feature = "derive")]
 pub use dyn_struct_derive::DynStruct;
 
+#[cfg(feature = "allocator-api2")]
+pub use allocator_api2;
+
+#[cfg(feature = "allocator-api2")]
+mod allocator;
+mod error;
+mod from_bytes;
+#[cfg(feature = "std")]
+mod io;
+mod map;
+mod raw;
+mod thin;
+mod vec;
+mod zeroable;
+
+pub use error::DynStructError;
+pub use from_bytes::FromBytes;
+#[cfg(target_has_atomic = "ptr")]
+pub use thin::{ThinArc, ThinArcDynStruct};
+pub use thin::{ThinBox, ThinDynStruct};
+pub use vec::DynVec;
+pub use zeroable::Zeroable;
+
+use alloc::borrow::ToOwned;
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+#[cfg(target_has_atomic = "ptr")]
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::alloc::Layout;
+use core::mem::MaybeUninit;
+use core::ops::{Index, IndexMut};
+use core::slice::SliceIndex;
+use raw::RawDst;
+use vec::RawVec;
+
+/// Items used by the code generated by the `DynStruct` derive macro, which cannot assume that the
+/// `alloc` crate is available under that name.
+#[doc(hidden)]
+pub mod __private {
+    pub use alloc::borrow::ToOwned;
+    pub use alloc::boxed::Box;
+    pub use alloc::rc::Rc;
+    #[cfg(target_has_atomic = "ptr")]
+    pub use alloc::sync::Arc;
+    pub use alloc::vec::Vec;
+    #[cfg(feature = "std")]
+    pub use std::io;
+
+    #[cfg(feature = "allocator-api2")]
+    pub use crate::allocator::new_in;
+    #[cfg(feature = "std")]
+    pub use crate::io::{read_from, write_to};
+    #[cfg(target_has_atomic = "ptr")]
+    pub use crate::raw::new_arc;
+    pub use crate::raw::{
+        as_bytes, from_bytes_with_len, from_iter, from_iter_exact, from_iter_exact_with,
+        from_iter_with, from_raw_parts, from_vec, into_raw_parts, layout_for, max_align, max_len,
+        new, new_rc, new_zeroed_with_header, tail_offset, try_new, RawDst,
+    };
+    pub use crate::thin::new_thin;
+    #[cfg(target_has_atomic = "ptr")]
+    pub use crate::thin::new_thin_arc;
+}
+
 #[repr(C)]
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct DynStruct<T, D> {
@@ -92,72 +202,569 @@ pub struct DynStruct<T, D> {
 }
 
 impl<T, D> DynStruct<T, D> {
+    /// Create a new value with the given `single` value, cloning each element of `many`.
     pub fn new(single: T, many: &[D]) -> Box<Self>
     where
-        T: Copy,
-        D: Copy,
+        D: Clone,
     {
-        use std::mem::{align_of, size_of};
+        raw::new(single, many)
+    }
 
-        let total_size = size_of::<T>() + size_of::<D>() * many.len();
+    /// Create a new value with the given `single` value and `len` elements, where the element at
+    /// index `i` is given by `element(i)`. The elements are written directly into the final
+    /// allocation.
+    ///
+    /// If `element` panics, the `single` value and the elements created so far are dropped.
+    pub fn new_with(single: T, len: usize, element: impl FnMut(usize) -> D) -> Box<Self> {
+        raw::new_with(single, len, element)
+    }
 
-        if total_size == 0 {
-            // Create a fat pointer to a slice of `many.len()` elements, then cast the slice into a
-            // fat pointer to `Self`. This essentially creates the fat pointer to `Self` of
-            // `many.len()` we need.
-            let slice: Box<[()]> = Box::from(slice_with_len(many.len()));
-            let ptr = Box::into_raw(slice) as *mut [()] as *mut Self;
-            unsafe { Box::from_raw(ptr) }
-        } else {
-            let align = usize::max(align_of::<T>(), align_of::<D>());
-            let layout = std::alloc::Layout::from_size_align(total_size, align).unwrap();
+    /// Allocate a value with `len` elements, without initializing it. The value can be
+    /// initialized through `single` and `many`, followed by a call to
+    /// [`DynStruct::assume_init`].
+    ///
+    /// Prefer [`DynStruct::new_with`], which does not require any `unsafe` code.
+    pub fn new_uninit(len: usize) -> Box<DynStruct<MaybeUninit<T>, MaybeUninit<D>>> {
+        let allocation = Allocation::new(raw::layout::<Self>(len));
+        unsafe { Box::from_raw(raw::fat_ptr(allocation.into_raw(), len)) }
+    }
+
+    /// Create a new value where both `single` and all `len` elements are zero, using
+    /// zero-initialized memory from the allocator.
+    pub fn new_zeroed(len: usize) -> Box<Self>
+    where
+        T: Zeroable,
+        D: Zeroable,
+    {
+        unsafe { raw::new_zeroed(len) }
+    }
+
+    /// Create a new value with the given `single` value, where all `len` elements are zero, using
+    /// zero-initialized memory from the allocator.
+    pub fn new_zeroed_with_header(single: T, len: usize) -> Box<Self>
+    where
+        D: Zeroable,
+    {
+        unsafe { raw::new_zeroed_with_header(single, len) }
+    }
 
-            unsafe {
-                let raw = std::alloc::alloc(layout);
-                if raw.is_null() {
-                    std::alloc::handle_alloc_error(layout)
-                }
+    /// Same as [`DynStruct::new`], but returns an error instead of panicking or aborting if the
+    /// value is too large, or if the allocation fails.
+    pub fn try_new(single: T, many: &[D]) -> Result<Box<Self>, DynStructError>
+    where
+        D: Clone,
+    {
+        raw::try_new(single, many)
+    }
 
-                Self::single_ptr(raw).copy_from_nonoverlapping(&single as *const T, 1);
-                Self::many_ptr(raw).copy_from_nonoverlapping(many.as_ptr(), many.len());
+    /// Create a new value with the given `single` value, moving the elements out of `many`.
+    pub fn from_vec(single: T, many: Vec<D>) -> Box<Self> {
+        raw::from_vec(single, many)
+    }
 
-                let slice = std::slice::from_raw_parts_mut(raw as *mut (), many.len());
-                let ptr = slice as *mut [()] as *mut Self;
-                Box::from_raw(ptr)
-            }
-        }
+    /// Create a new value with the given `single` value, writing the elements of `many` directly
+    /// into the final allocation.
+    ///
+    /// The reported length of the iterator is only used as a hint: if it yields fewer or more
+    /// elements than it claims to, the allocation is shrunk or grown to fit.
+    pub fn from_iter_exact<I>(single: T, many: I) -> Box<Self>
+    where
+        I: IntoIterator<Item = D>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        raw::from_iter_exact(single, many)
     }
 
-    fn single_ptr(raw: *mut u8) -> *mut T {
-        raw as *mut T
+    /// Create a new value with the given `single` value and the elements of `many`.
+    ///
+    /// The elements are collected into a buffer which grows as needed, and is shrunk to fit at
+    /// the end. Prefer [`DynStruct::from_iter_exact`] if the number of elements is known upfront.
+    pub fn from_iter<I>(single: T, many: I) -> Box<Self>
+    where
+        I: IntoIterator<Item = D>,
+    {
+        raw::from_iter(single, many)
     }
 
-    fn many_ptr(raw: *mut u8) -> *mut D {
+    /// Resize `many` to `new_len` elements, either by dropping the elements past `new_len`, or by
+    /// appending clones of `fill`. The allocation is resized to fit exactly with a single
+    /// `realloc`, and `single` is kept as is.
+    pub fn resize(self: Box<Self>, new_len: usize, fill: D) -> Box<Self>
+    where
+        D: Clone,
+    {
+        let mut vec = RawVec::from_box(self);
+        vec.resize(new_len, fill);
+        vec.into_box()
+    }
+
+    /// Drop all elements after the first `len`, and shrink the allocation with `realloc`. Does
+    /// nothing if there are fewer than `len` elements.
+    pub fn truncate(self: Box<Self>, len: usize) -> Box<Self> {
+        let mut vec = RawVec::from_box(self);
+        vec.truncate(len);
+        vec.into_box()
+    }
+
+    /// Append a clone of each element in `extra`, growing the allocation to fit exactly with a
+    /// single `realloc`.
+    pub fn append(self: Box<Self>, extra: &[D]) -> Box<Self>
+    where
+        D: Clone,
+    {
+        let mut vec = RawVec::from_box(self);
+        vec.reserve_exact(extra.len());
+        vec.extend(extra.iter().cloned());
+        vec.into_box()
+    }
+
+    /// Create a new value with the given `single` value inside the memory of `buffer`, cloning
+    /// each element of `many`. The value starts at the beginning of `buffer`.
+    ///
+    /// Returns an error if `buffer` is too small, or is not aligned for `Self`. Note that the
+    /// returned value is never dropped, unless done so explicitly with `core::ptr::drop_in_place`.
+    pub fn init_in<'a>(
+        buffer: &'a mut [MaybeUninit<u8>],
+        single: T,
+        many: &[D],
+    ) -> Result<&'a mut Self, DynStructError>
+    where
+        D: Clone,
+    {
+        let layout = raw::try_layout::<Self>(many.len())?;
+
+        let raw = buffer.as_mut_ptr() as *mut u8;
+        if raw.align_offset(layout.align()) != 0 {
+            return Err(DynStructError::MisalignedBuffer(layout));
+        }
+        if buffer.len() < layout.size() {
+            return Err(DynStructError::BufferTooSmall(layout));
+        }
+
         unsafe {
-            let naive = raw.add(std::mem::size_of::<T>());
-            let align = std::mem::align_of::<D>();
-            let ptr = naive.add(naive.align_offset(align));
-            ptr as *mut D
+            raw::init_with::<Self>(raw, single, many.len(), |i| many[i].clone());
+            Ok(&mut *raw::fat_ptr(raw, many.len()))
         }
     }
+
+    /// View `bytes` as a value, without copying them. The number of elements is the largest one
+    /// for which the value has a size of `bytes.len()`.
+    ///
+    /// Returns an error if `bytes` is not aligned for `Self`, is too small to fit `single`, or
+    /// its length does not fit a whole number of elements (including any padding at the end). If
+    /// `D` is zero-sized, the value has no elements.
+    pub fn from_bytes(bytes: &[u8]) -> Result<&Self, DynStructError>
+    where
+        T: FromBytes,
+        D: FromBytes,
+    {
+        let len = Self::len_of_bytes(bytes.as_ptr(), bytes.len())?;
+        unsafe { Ok(&*raw::fat_ptr(bytes.as_ptr() as *mut u8, len)) }
+    }
+
+    /// Same as [`DynStruct::from_bytes`], but returns a mutable view.
+    pub fn from_bytes_mut(bytes: &mut [u8]) -> Result<&mut Self, DynStructError>
+    where
+        T: FromBytes,
+        D: FromBytes,
+    {
+        let len = Self::len_of_bytes(bytes.as_ptr(), bytes.len())?;
+        unsafe { Ok(&mut *raw::fat_ptr(bytes.as_mut_ptr(), len)) }
+    }
+
+    /// View the start of `bytes` as a value with `len` elements, without copying them. Returns
+    /// the value and the remaining bytes after it.
+    ///
+    /// Returns an error if `bytes` is not aligned for `Self`, or is too small to fit the value.
+    pub fn from_bytes_prefix(bytes: &[u8], len: usize) -> Result<(&Self, &[u8]), DynStructError>
+    where
+        T: FromBytes,
+        D: FromBytes,
+    {
+        unsafe { raw::from_bytes_prefix(bytes, len) }
+    }
+
+    /// View the value as bytes, including the header and all elements. This is the inverse of
+    /// [`DynStruct::from_bytes`].
+    ///
+    /// Panics if the value contains padding bytes, which are not guaranteed to be initialized.
+    /// Depending on the alignment of `single` and the elements, this can be the case for only
+    /// some numbers of elements.
+    pub fn as_bytes(&self) -> &[u8]
+    where
+        T: FromBytes,
+        D: FromBytes,
+    {
+        unsafe { raw::as_bytes(self) }
+    }
+
+    /// Same as [`DynStruct::new`], but the value is built directly inside the allocation of an
+    /// `Rc`, instead of being copied there from a `Box`.
+    pub fn new_rc(single: T, many: &[D]) -> Rc<Self>
+    where
+        D: Clone,
+    {
+        raw::new_rc(single, many)
+    }
+
+    /// Same as [`DynStruct::new`], but the value is built directly inside the allocation of an
+    /// `Arc`, instead of being copied there from a `Box`.
+    #[cfg(target_has_atomic = "ptr")]
+    pub fn new_arc(single: T, many: &[D]) -> Arc<Self>
+    where
+        D: Clone,
+    {
+        raw::new_arc(single, many)
+    }
+
+    /// The number of elements in `many`.
+    pub fn len(&self) -> usize {
+        self.many.len()
+    }
+
+    /// Returns `true` if there are no elements in `many`.
+    pub fn is_empty(&self) -> bool {
+        self.many.is_empty()
+    }
+
+    /// The layout of a value with `len` elements in `many`. This matches what the compiler uses
+    /// for `#[repr(C)]` structs, including the padding between the fields and at the end, so it is
+    /// the same as `Layout::for_value` of such a value.
+    ///
+    /// Returns an error if the size of the value would exceed `isize::MAX`.
+    pub const fn layout_for(len: usize) -> Result<Layout, DynStructError> {
+        raw::layout_for(
+            Self::tail_offset(),
+            Self::align(),
+            core::mem::size_of::<D>(),
+            len,
+        )
+    }
+
+    /// The offset in bytes of `many` from the start of the value, which is the same for any
+    /// number of elements.
+    pub const fn tail_offset() -> usize {
+        raw::tail_offset(core::mem::size_of::<T>(), core::mem::align_of::<D>())
+    }
+
+    /// The largest number of elements for which [`DynStruct::layout_for`] succeeds. This is
+    /// `usize::MAX` if `D` is zero-sized.
+    pub const fn max_len() -> usize {
+        raw::max_len(
+            Self::tail_offset(),
+            Self::align(),
+            core::mem::size_of::<D>(),
+        )
+    }
+
+    /// The alignment of the value, which is the same for any number of elements.
+    const fn align() -> usize {
+        raw::max_align(core::mem::align_of::<T>(), core::mem::align_of::<D>())
+    }
+
+    /// The number of elements of a value which starts at `data` and has a size of `size` bytes.
+    fn len_of_bytes(data: *const u8, size: usize) -> Result<usize, DynStructError> {
+        let header = Self::layout_for(0)?;
+        if data.align_offset(header.align()) != 0 {
+            return Err(DynStructError::MisalignedBuffer(header));
+        }
+        if size < header.size() {
+            return Err(DynStructError::BufferTooSmall(header));
+        }
+
+        let len = match core::mem::size_of::<D>() {
+            0 => 0,
+            element => (size - Self::tail_offset()) / element,
+        };
+        if Self::layout_for(len)?.size() != size {
+            return Err(DynStructError::SizeMismatch(size));
+        }
+        Ok(len)
+    }
+
+    /// Consume the box, returning a pointer to the value and the number of elements in `many`.
+    /// The box can be recreated with [`DynStruct::from_raw_parts`].
+    pub fn into_raw_parts(this: Box<Self>) -> (*mut u8, usize) {
+        raw::into_raw_parts(this)
+    }
+
+    /// Recreate a box from the parts returned by [`DynStruct::into_raw_parts`].
+    ///
+    /// # Safety
+    ///
+    /// `data` and `len` have to be returned by `into_raw_parts` for a box of the same type, and
+    /// the box may only be recreated once.
+    pub unsafe fn from_raw_parts(data: *mut u8, len: usize) -> Box<Self> {
+        raw::from_raw_parts(data, len)
+    }
+
+    /// View the value at `data` with `len` elements in `many`.
+    ///
+    /// # Safety
+    ///
+    /// `data` has to point to an initialized value with `len` elements, which lives for `'a` and
+    /// is not mutated in the meantime.
+    pub unsafe fn from_raw_parts_ref<'a>(data: *const u8, len: usize) -> &'a Self {
+        &*Self::ptr_from_raw_parts(data as *mut u8, len)
+    }
+
+    /// Same as [`DynStruct::from_raw_parts_ref`], but returns a mutable reference.
+    ///
+    /// # Safety
+    ///
+    /// `data` has to point to an initialized value with `len` elements, which lives for `'a` and
+    /// is not accessed through any other pointer in the meantime.
+    pub unsafe fn from_raw_parts_mut<'a>(data: *mut u8, len: usize) -> &'a mut Self {
+        &mut *Self::ptr_from_raw_parts(data, len)
+    }
+
+    /// Create a pointer to a value at `data` with `len` elements in `many`. This is always safe,
+    /// but the pointer is only valid to dereference if it points to such a value.
+    pub const fn ptr_from_raw_parts(data: *mut u8, len: usize) -> *mut Self {
+        // Create a fat pointer to a slice of `len` elements, then cast the slice into a fat
+        // pointer to `Self`. This essentially creates the fat pointer to `Self` of `len` we need.
+        core::ptr::slice_from_raw_parts_mut(data as *mut (), len) as *mut Self
+    }
+}
+
+/// Dynamically sized types which end in a slice, and whose pointers therefore carry the length
+/// of that slice. This is implemented for [`DynStruct`](struct@crate::DynStruct), and by the
+/// `DynStruct` derive macro.
+///
+/// # Safety
+///
+/// The functions have to be implemented such that
+/// `Self::ptr_len(Self::ptr_from_raw_parts(data, len)) == len`, and the resulting pointer points to
+/// `data`.
+pub unsafe trait SliceDst {
+    /// Create a pointer to a value at `data` which has `len` elements in its trailing slice.
+    fn ptr_from_raw_parts(data: *mut u8, len: usize) -> *mut Self;
+
+    /// The number of elements in the trailing slice of the value `ptr` points to.
+    fn ptr_len(ptr: *const Self) -> usize;
+}
+
+unsafe impl<T, D> SliceDst for DynStruct<T, D> {
+    fn ptr_from_raw_parts(data: *mut u8, len: usize) -> *mut Self {
+        Self::ptr_from_raw_parts(data, len)
+    }
+
+    fn ptr_len(ptr: *const Self) -> usize {
+        (ptr as *const [()]).len()
+    }
+}
+
+unsafe impl<T, D> RawDst for DynStruct<T, D> {
+    type Header = T;
+    type Element = D;
+    const TAIL_OFFSET: usize = Self::tail_offset();
+}
+
+impl<T: Clone, D: Clone> Clone for Box<DynStruct<T, D>> {
+    fn clone(&self) -> Self {
+        DynStruct::new(self.single.clone(), &self.many)
+    }
+}
+
+/// A value with the default `single` value and no elements.
+impl<T: Default, D> Default for Box<DynStruct<T, D>> {
+    fn default() -> Self {
+        DynStruct::from_vec(T::default(), Vec::new())
+    }
+}
+
+impl<T: Clone, D: Clone> ToOwned for DynStruct<T, D> {
+    type Owned = Box<Self>;
+
+    fn to_owned(&self) -> Box<Self> {
+        DynStruct::new(self.single.clone(), &self.many)
+    }
+}
+
+impl<T, D, I: SliceIndex<[D]>> Index<I> for DynStruct<T, D> {
+    type Output = I::Output;
+
+    fn index(&self, index: I) -> &I::Output {
+        &self.many[index]
+    }
+}
+
+impl<T, D, I: SliceIndex<[D]>> IndexMut<I> for DynStruct<T, D> {
+    fn index_mut(&mut self, index: I) -> &mut I::Output {
+        &mut self.many[index]
+    }
+}
+
+impl<'a, T, D> IntoIterator for &'a DynStruct<T, D> {
+    type Item = &'a D;
+    type IntoIter = core::slice::Iter<'a, D>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.many.iter()
+    }
+}
+
+impl<'a, T, D> IntoIterator for &'a mut DynStruct<T, D> {
+    type Item = &'a mut D;
+    type IntoIter = core::slice::IterMut<'a, D>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.many.iter_mut()
+    }
+}
+
+impl<T, D> AsRef<[D]> for DynStruct<T, D> {
+    fn as_ref(&self) -> &[D] {
+        &self.many
+    }
+}
+
+impl<T, D> AsMut<[D]> for DynStruct<T, D> {
+    fn as_mut(&mut self) -> &mut [D] {
+        &mut self.many
+    }
+}
+
+impl<T, D> DynStruct<MaybeUninit<T>, MaybeUninit<D>> {
+    /// Convert a value created by [`DynStruct::new_uninit`] into an initialized value.
+    ///
+    /// # Safety
+    ///
+    /// Both `single` and every element of `many` have to be initialized. Otherwise, this causes
+    /// undefined behaviour.
+    pub unsafe fn assume_init(self: Box<Self>) -> Box<DynStruct<T, D>> {
+        let len = self.many.len();
+        let raw = Box::into_raw(self) as *mut u8;
+        Box::from_raw(raw::fat_ptr(raw, len))
+    }
 }
 
 impl<T> DynStruct<T, T> {
     /// Get a `DynStruct` as a view over a slice (this does not allocate).
     pub fn from_slice(values: &[T]) -> &Self {
-        assert!(
-            !values.is_empty(),
+        Self::try_from_slice(values).unwrap_or_else(|| Self::empty_slice())
+    }
+
+    /// Same as [`DynStruct::from_slice`], but returns a mutable view.
+    pub fn from_slice_mut(values: &mut [T]) -> &mut Self {
+        match values.len() {
+            0 => Self::empty_slice(),
+            len => unsafe {
+                &mut *Self::ptr_from_raw_parts(values.as_mut_ptr() as *mut u8, len - 1)
+            },
+        }
+    }
+
+    /// Same as [`DynStruct::from_slice`], but returns `None` if `values` is empty.
+    pub fn try_from_slice(values: &[T]) -> Option<&Self> {
+        match values.len() {
+            0 => None,
+            len => Some(unsafe { &*Self::ptr_from_raw_parts(values.as_ptr() as *mut u8, len - 1) }),
+        }
+    }
+
+    /// Convert a boxed slice into a `DynStruct`, reusing its allocation. The first element
+    /// becomes the `single` value. Panics if `values` is empty.
+    pub fn from_boxed_slice(values: Box<[T]>) -> Box<Self> {
+        if values.is_empty() {
+            Self::empty_slice()
+        }
+
+        // `Self` has the same layout as a slice of `many.len() + 1` elements
+        let len = values.len();
+        let raw = Box::into_raw(values) as *mut u8;
+        unsafe { Box::from_raw(Self::ptr_from_raw_parts(raw, len - 1)) }
+    }
+
+    /// Convert the value into a boxed slice with the `single` value as its first element, reusing
+    /// the allocation.
+    pub fn into_boxed_slice(self: Box<Self>) -> Box<[T]> {
+        let len = self.many.len() + 1;
+        let raw = Box::into_raw(self) as *mut T;
+        unsafe { Box::from_raw(core::ptr::slice_from_raw_parts_mut(raw, len)) }
+    }
+
+    fn empty_slice() -> ! {
+        panic!(
             "attempted to create `{}` without `single` value (`values.is_empty()`)",
-            std::any::type_name::<Self>()
-        );
-        let slice = &values[..values.len() - 1];
-        unsafe { &*(slice as *const [T] as *const Self) }
+            core::any::type_name::<Self>()
+        )
+    }
+}
+
+impl<D, const N: usize> DynStruct<[D; N], D> {
+    /// View a slice as a `DynStruct`, where the first `N` elements make up the `single` value and
+    /// the rest are in `many` (this does not allocate). Returns `None` if there are fewer than `N`
+    /// elements.
+    pub fn from_prefixed_slice(values: &[D]) -> Option<&Self> {
+        let len = values.len().checked_sub(N)?;
+        Some(unsafe { &*Self::ptr_from_raw_parts(values.as_ptr() as *mut u8, len) })
+    }
+
+    /// Same as [`DynStruct::from_prefixed_slice`], but returns a mutable view.
+    pub fn from_prefixed_slice_mut(values: &mut [D]) -> Option<&mut Self> {
+        let len = values.len().checked_sub(N)?;
+        Some(unsafe { &mut *Self::ptr_from_raw_parts(values.as_mut_ptr() as *mut u8, len) })
     }
 }
 
-fn slice_with_len(len: usize) -> &'static [()] {
-    static ARBITRARY: [(); usize::MAX] = [(); usize::MAX];
-    &ARBITRARY[..len]
+/// An uninitialized allocation with a given layout, which is freed again if dropped.
+struct Allocation {
+    raw: *mut u8,
+    layout: Layout,
+}
+
+impl Allocation {
+    fn new(layout: Layout) -> Self {
+        match Allocation::try_new(layout) {
+            Ok(allocation) => allocation,
+            Err(_) => alloc::alloc::handle_alloc_error(layout),
+        }
+    }
+
+    fn new_zeroed(layout: Layout) -> Self {
+        match Allocation::allocate(layout, alloc::alloc::alloc_zeroed) {
+            Ok(allocation) => allocation,
+            Err(_) => alloc::alloc::handle_alloc_error(layout),
+        }
+    }
+
+    fn try_new(layout: Layout) -> Result<Self, DynStructError> {
+        Allocation::allocate(layout, alloc::alloc::alloc)
+    }
+
+    fn allocate(
+        layout: Layout,
+        alloc: unsafe fn(Layout) -> *mut u8,
+    ) -> Result<Self, DynStructError> {
+        let raw = if layout.size() == 0 {
+            // Zero-sized values never touch the allocator, but the pointer still has to be
+            // properly aligned.
+            layout.align() as *mut u8
+        } else {
+            let raw = unsafe { alloc(layout) };
+            if raw.is_null() {
+                return Err(DynStructError::AllocError(layout));
+            }
+            raw
+        };
+        Ok(Allocation { raw, layout })
+    }
+
+    /// Take ownership of the allocation.
+    fn into_raw(self) -> *mut u8 {
+        let raw = self.raw;
+        core::mem::forget(self);
+        raw
+    }
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        if self.layout.size() != 0 {
+            unsafe { alloc::alloc::dealloc(self.raw, self.layout) }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -178,10 +785,752 @@ mod tests {
         assert_eq!(&zero.many, &[(), ()]);
     }
 
+    #[test]
+    fn padding() {
+        let padded = DynStruct::new(1u8, &[2u32, 3, 4]);
+        assert_eq!(padded.single, 1);
+        assert_eq!(&padded.many, &[2, 3, 4]);
+        assert_eq!(std::mem::size_of_val(&*padded), 16);
+
+        let trailing = DynStruct::new(1u64, &[2u8, 3, 4]);
+        assert_eq!(&trailing.many, &[2, 3, 4]);
+        assert_eq!(std::mem::size_of_val(&*trailing), 16);
+    }
+
+    #[test]
+    fn reference_counted() {
+        let rc = DynStruct::new_rc((true, 32u64), &[1u8, 2, 3]);
+        assert_eq!(rc.single, (true, 32u64));
+        assert_eq!(&rc.many, &[1, 2, 3]);
+        assert_eq!(std::mem::size_of_val(&*rc), 24);
+
+        let arc = DynStruct::new_arc(7u16, &[1u32, 2]);
+        let clone = Arc::clone(&arc);
+        assert_eq!(clone.single, 7);
+        assert_eq!(&clone.many, &[1, 2]);
+
+        let zero = DynStruct::new_arc((), &[(), (), ()]);
+        assert_eq!(zero.many.len(), 3);
+    }
+
+    #[test]
+    fn non_copy() {
+        let owned = DynStruct::new(String::from("header"), &[vec![1], vec![2, 3]]);
+        assert_eq!(owned.single, "header");
+        assert_eq!(&owned.many, &[vec![1], vec![2, 3]]);
+
+        let moved = DynStruct::from_vec(Rc::new(1), vec![String::from("a"), String::from("b")]);
+        assert_eq!(*moved.single, 1);
+        assert_eq!(&moved.many, &["a", "b"]);
+    }
+
+    #[test]
+    fn drops_everything() {
+        let counter = Rc::new(());
+        let value = DynStruct::new(
+            Rc::clone(&counter),
+            &[Rc::clone(&counter), Rc::clone(&counter)],
+        );
+        let moved = DynStruct::from_vec(Rc::clone(&counter), vec![Rc::clone(&counter)]);
+        assert_eq!(Rc::strong_count(&counter), 6);
+        drop(value);
+        drop(moved);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn panic_while_cloning() {
+        struct Bomb(Rc<()>, bool);
+
+        impl Clone for Bomb {
+            fn clone(&self) -> Self {
+                assert!(!self.1, "boom");
+                Bomb(Rc::clone(&self.0), self.1)
+            }
+        }
+
+        let counter = Rc::new(());
+        let many = [
+            Bomb(Rc::clone(&counter), false),
+            Bomb(Rc::clone(&counter), true),
+        ];
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            DynStruct::new(Rc::clone(&counter), &many)
+        }));
+        assert!(result.is_err());
+        assert_eq!(Rc::strong_count(&counter), 3);
+    }
+
+    #[test]
+    fn from_iter() {
+        let exact = DynStruct::from_iter_exact(String::from("exact"), (1..5).map(|i| i * 2));
+        assert_eq!(exact.single, "exact");
+        assert_eq!(&exact.many, &[2, 4, 6, 8]);
+
+        let filtered = DynStruct::from_iter(1u8, (0..100u64).filter(|i| i % 10 == 0));
+        assert_eq!(filtered.single, 1);
+        assert_eq!(&filtered.many, &[0, 10, 20, 30, 40, 50, 60, 70, 80, 90]);
+
+        let empty = DynStruct::from_iter(1u8, std::iter::empty::<u32>());
+        assert_eq!(empty.many.len(), 0);
+
+        let zero = DynStruct::from_iter((), std::iter::repeat_n((), 1000));
+        assert_eq!(zero.many.len(), 1000);
+    }
+
+    /// An iterator which reports `reported` elements, but yields `actual`.
+    struct Liar {
+        reported: usize,
+        actual: std::ops::Range<u32>,
+    }
+
+    impl Iterator for Liar {
+        type Item = u32;
+
+        fn next(&mut self) -> Option<u32> {
+            self.actual.next()
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.reported, Some(self.reported))
+        }
+    }
+
+    impl ExactSizeIterator for Liar {}
+
+    #[test]
+    fn from_iter_exact_wrong_len() {
+        let short = DynStruct::from_iter_exact(
+            7u8,
+            Liar {
+                reported: 10,
+                actual: 0..3,
+            },
+        );
+        assert_eq!(&short.many, &[0, 1, 2]);
+        assert_eq!(std::mem::size_of_val(&*short), 16);
+
+        let long = DynStruct::from_iter_exact(
+            7u8,
+            Liar {
+                reported: 2,
+                actual: 0..9,
+            },
+        );
+        assert_eq!(&long.many, &[0, 1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn panic_while_iterating() {
+        let counter = Rc::new(());
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let many = (0..10).map(|i| {
+                assert!(i < 5, "boom");
+                Rc::clone(&counter)
+            });
+            DynStruct::from_iter_exact(Rc::clone(&counter), many)
+        }));
+        assert!(result.is_err());
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn try_new() {
+        let value = DynStruct::try_new(1u8, &[2u32, 3]).unwrap();
+        assert_eq!(value.single, 1);
+        assert_eq!(&value.many, &[2, 3]);
+
+        assert_eq!(
+            DynStruct::<u8, u64>::layout_for(usize::MAX / 4),
+            Err(DynStructError::LayoutOverflow)
+        );
+        assert_eq!(
+            DynStruct::<u8, u8>::layout_for(isize::MAX as usize),
+            Err(DynStructError::LayoutOverflow)
+        );
+
+        assert_eq!(
+            DynStructError::AllocError(Layout::new::<u64>()).to_string(),
+            "failed to allocate 8 bytes with an alignment of 8"
+        );
+    }
+
+    #[test]
+    fn layout() {
+        fn check<T: Clone, D: Clone>(single: T, element: D) {
+            for len in 0..5 {
+                let value = DynStruct::new(single.clone(), &vec![element.clone(); len]);
+                let layout = DynStruct::<T, D>::layout_for(len).unwrap();
+                assert_eq!(layout.size(), std::mem::size_of_val(&*value));
+                assert_eq!(layout.align(), std::mem::align_of_val(&*value));
+
+                let start = std::ptr::addr_of!(*value) as *const u8;
+                let many = std::ptr::addr_of!(value.many) as *const u8;
+                assert_eq!(
+                    many as usize - start as usize,
+                    DynStruct::<T, D>::tail_offset()
+                );
+            }
+
+            let max_len = DynStruct::<T, D>::max_len();
+            assert!(DynStruct::<T, D>::layout_for(max_len).is_ok());
+            if max_len != usize::MAX {
+                assert!(DynStruct::<T, D>::layout_for(max_len + 1).is_err());
+            }
+        }
+
+        check(1u8, 2u32);
+        check(1u32, 2u8);
+        check((1u8, 2u64), 3u16);
+        check(1u16, (2u8, 3u32));
+        check((), 1u64);
+        check(1u64, ());
+        check([1u8; 3], [2u16; 3]);
+
+        const LAYOUT: Layout = match DynStruct::<u8, u32>::layout_for(3) {
+            Ok(layout) => layout,
+            Err(_) => panic!(),
+        };
+        const TAIL_OFFSET: usize = DynStruct::<u8, u32>::tail_offset();
+        assert_eq!(LAYOUT, Layout::from_size_align(16, 4).unwrap());
+        assert_eq!(TAIL_OFFSET, 4);
+    }
+
+    #[test]
+    fn raw_parts() {
+        let value = DynStruct::new(String::from("raw"), &[1u16, 2, 3]);
+        let (data, len) = DynStruct::into_raw_parts(value);
+        assert_eq!(len, 3);
+
+        unsafe {
+            let value = DynStruct::<String, u16>::from_raw_parts_mut(data, len);
+            value.many[0] = 4;
+            let value = DynStruct::<String, u16>::from_raw_parts_ref(data, len);
+            assert_eq!(&value.many, &[4, 2, 3]);
+
+            let ptr = DynStruct::<String, u16>::ptr_from_raw_parts(data, len);
+            assert_eq!(ptr as *mut u8, data);
+            assert_eq!(std::mem::size_of_val(&*ptr), 32);
+
+            let value = DynStruct::<String, u16>::from_raw_parts(data, len);
+            assert_eq!(value.single, "raw");
+            assert_eq!(&value.many, &[4, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn new_with() {
+        let squares = DynStruct::new_with(String::from("squares"), 5, |i| i * i);
+        assert_eq!(squares.single, "squares");
+        assert_eq!(&squares.many, &[0, 1, 4, 9, 16]);
+
+        let counter = Rc::new(());
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            DynStruct::new_with(Rc::clone(&counter), 10, |i| {
+                assert!(i < 5, "boom");
+                Rc::clone(&counter)
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn new_uninit() {
+        let mut uninit = DynStruct::<String, u32>::new_uninit(3);
+        uninit.single.write(String::from("uninit"));
+        for (i, element) in uninit.many.iter_mut().enumerate() {
+            element.write(i as u32 + 1);
+        }
+        let value = unsafe { uninit.assume_init() };
+        assert_eq!(value.single, "uninit");
+        assert_eq!(&value.many, &[1, 2, 3]);
+        assert_eq!(std::mem::size_of_val(&*value), 40);
+    }
+
+    #[test]
+    fn new_zeroed() {
+        let zeroed = DynStruct::<(u8, f64), [u16; 3]>::new_zeroed(4);
+        assert_eq!(zeroed.single, (0, 0.0));
+        assert_eq!(&zeroed.many, &[[0; 3]; 4]);
+
+        let header = DynStruct::new_zeroed_with_header(String::from("header"), 1000);
+        assert_eq!(header.single, "header");
+        assert_eq!(header.many.len(), 1000);
+        assert!(header.many.iter().all(|&element: &u64| element == 0));
+
+        let pointers = DynStruct::<Option<&u8>, *const u8>::new_zeroed(2);
+        assert_eq!(pointers.single, None);
+        assert!(pointers.many.iter().all(|element| element.is_null()));
+    }
+
+    #[test]
+    fn init_in() {
+        #[repr(C, align(8))]
+        struct Buffer([MaybeUninit<u8>; 32]);
+
+        let mut buffer = Buffer([MaybeUninit::uninit(); 32]);
+        let value = DynStruct::init_in(&mut buffer.0, 1u32, &[2u64, 3, 4]).unwrap();
+        assert_eq!(value.single, 1);
+        assert_eq!(&value.many, &[2, 3, 4]);
+        value.many[0] = 5;
+        assert_eq!(&value.many, &[5, 3, 4]);
+
+        let layout = Layout::from_size_align(40, 8).unwrap();
+        assert_eq!(
+            DynStruct::init_in(&mut buffer.0, 1u32, &[2u64, 3, 4, 5]).unwrap_err(),
+            DynStructError::BufferTooSmall(layout)
+        );
+
+        let layout = Layout::from_size_align(24, 8).unwrap();
+        assert_eq!(
+            DynStruct::init_in(&mut buffer.0[4..], 1u32, &[2u64, 3]).unwrap_err(),
+            DynStructError::MisalignedBuffer(layout)
+        );
+    }
+
+    #[test]
+    fn thin_box() {
+        assert_eq!(
+            std::mem::size_of::<Option<ThinDynStruct<u8, u32>>>(),
+            std::mem::size_of::<usize>()
+        );
+
+        let mut thin = ThinDynStruct::new(String::from("thin"), &[1u128, 2, 3]);
+        assert_eq!(thin.single, "thin");
+        assert_eq!(&thin.many, &[1, 2, 3]);
+        thin.many[1] = 5;
+
+        let clone = thin.clone();
+        assert_eq!(clone.single, "thin");
+        assert_eq!(&clone.many, &[1, 5, 3]);
+        assert_eq!(format!("{:?}", clone), format!("{:?}", &*thin));
+
+        let boxed = ThinBox::from_box(DynStruct::new(1u8, &[2u16, 3]));
+        assert_eq!(boxed.single, 1);
+        assert_eq!(&boxed.many, &[2, 3]);
+
+        let zero = ThinDynStruct::new((), &[(), ()]);
+        assert_eq!(zero.many.len(), 2);
+
+        let counter = Rc::new(());
+        let thin = ThinDynStruct::new(Rc::clone(&counter), &[Rc::clone(&counter)]);
+        let boxed = ThinBox::from_box(DynStruct::<_, u8>::new(Rc::clone(&counter), &[]));
+        assert_eq!(Rc::strong_count(&counter), 4);
+        drop((thin, boxed));
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn thin_arc() {
+        assert_eq!(
+            std::mem::size_of::<Option<ThinArcDynStruct<u8, u32>>>(),
+            std::mem::size_of::<usize>()
+        );
+
+        let mut thin = ThinArcDynStruct::new(String::from("thin"), &[1u128, 2, 3]);
+        ThinArc::get_mut(&mut thin).unwrap().many[1] = 5;
+
+        let clones = (0..4)
+            .map(|_| {
+                let thin = thin.clone();
+                std::thread::spawn(move || {
+                    assert_eq!(thin.single, "thin");
+                    assert_eq!(&thin.many, &[1, 5, 3]);
+                    thin
+                })
+            })
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ThinArc::strong_count(&thin), 5);
+        assert!(clones.iter().all(|clone| ThinArc::ptr_eq(clone, &thin)));
+        assert!(ThinArc::get_mut(&mut thin).is_none());
+        drop(clones);
+        assert_eq!(ThinArc::strong_count(&thin), 1);
+
+        let boxed = ThinArc::from_box(DynStruct::new(1u8, &[2u16, 3]));
+        assert_eq!(boxed.single, 1);
+        assert_eq!(&boxed.many, &[2, 3]);
+
+        let counter = Rc::new(());
+        let thin = ThinArcDynStruct::new(Rc::clone(&counter), &[Rc::clone(&counter)]);
+        let clone = thin.clone();
+        assert_eq!(Rc::strong_count(&counter), 3);
+        drop(thin);
+        assert_eq!(Rc::strong_count(&counter), 3);
+        drop(clone);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn dyn_vec() {
+        let mut vec = DynVec::with_capacity(String::from("vec"), 2);
+        assert!(vec.is_empty());
+        assert_eq!(vec.capacity(), 2);
+
+        vec.push(String::from("a"));
+        vec.extend_from_slice(&[String::from("b"), String::from("c")]);
+        assert_eq!(vec.len(), 3);
+        assert!(vec.capacity() >= 3);
+        assert_eq!(vec.single, "vec");
+        assert_eq!(&vec.many, &["a", "b", "c"]);
+
+        vec.single.push('!');
+        vec.many[0].push('!');
+        vec.truncate(2);
+        vec.reserve(100);
+        assert!(vec.capacity() >= 102);
+        vec.shrink_to_fit();
+        assert_eq!(vec.capacity(), 2);
+
+        let boxed = vec.into_box();
+        assert_eq!(boxed.single, "vec!");
+        assert_eq!(&boxed.many, &["a!", "b"]);
+        assert_eq!(std::mem::size_of_val(&*boxed), 72);
+
+        let mut vec = DynVec::from_box(boxed);
+        assert_eq!(vec.capacity(), 2);
+        vec.push(String::from("c"));
+        assert_eq!(&vec.many, &["a!", "b", "c"]);
+        assert_eq!(std::mem::size_of_val(&vec), std::mem::size_of::<usize>());
+        let aligned = DynVec::<u8, u128>::from_box(DynStruct::new(1, &[2]));
+        assert_eq!(aligned.into_box().many, [2]);
+        let mut bytes = DynVec::<u8, u8>::with_capacity(1, 4);
+        bytes.extend_from_slice(&[2, 3]);
+        assert_eq!(&bytes.into_box().many, &[2, 3]);
+        let bytes = DynVec::<u8, u8>::from_box(DynStruct::new(1, &[2, 3]));
+        assert_eq!(&bytes.into_box().many, &[2, 3]);
+
+        let mut zero = DynVec::new(());
+        zero.extend(std::iter::repeat_n((), 10));
+        assert_eq!(zero.len(), 10);
+        assert_eq!(zero.capacity(), usize::MAX);
+
+        let counter = Rc::new(());
+        let mut vec = DynVec::new(Rc::clone(&counter));
+        vec.extend((0..10).map(|_| Rc::clone(&counter)));
+        vec.truncate(5);
+        assert_eq!(Rc::strong_count(&counter), 7);
+        drop(vec);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn resize() {
+        let boxed = DynStruct::new(String::from("header"), &[String::from("a")]);
+        let boxed = boxed.append(&[String::from("b"), String::from("c")]);
+        assert_eq!(boxed.single, "header");
+        assert_eq!(&boxed.many, &["a", "b", "c"]);
+
+        let boxed = boxed.resize(5, String::from("x"));
+        assert_eq!(&boxed.many, &["a", "b", "c", "x", "x"]);
+        assert_eq!(std::mem::size_of_val(&*boxed), 24 * 6);
+
+        let boxed = boxed.resize(1, String::from("y"));
+        assert_eq!(&boxed.many, &["a"]);
+        let boxed = boxed.truncate(0);
+        assert_eq!(boxed.single, "header");
+        assert!(boxed.many.is_empty());
+        let boxed = boxed.truncate(3);
+        assert!(boxed.many.is_empty());
+
+        let zero = DynStruct::new(1u8, &[(); 3]).resize(7, ());
+        assert_eq!(zero.many.len(), 7);
+
+        let counter = Rc::new(());
+        let boxed = DynStruct::new(Rc::clone(&counter), &[Rc::clone(&counter)]);
+        let boxed = boxed.resize(10, Rc::clone(&counter)).truncate(4);
+        assert_eq!(Rc::strong_count(&counter), 6);
+        drop(boxed);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn parts() {
+        let mut value = DynStruct::new(0u32, &[1u32, 2, 3]);
+        let (sum, many) = value.parts_mut();
+        for element in many.iter_mut() {
+            *sum += *element;
+            *element *= 2;
+        }
+        assert_eq!(value.parts(), (&6, &[2, 4, 6][..]));
+    }
+
+    #[test]
+    fn map_header() {
+        let value = DynStruct::new(1u32, &[String::from("a"), String::from("b")]);
+        let ptr = &*value as *const _ as *const u8;
+        let same = value.map_header(|single| single as i32 - 2);
+        assert_eq!(same.single, -1);
+        assert_eq!(&same.many, &["a", "b"]);
+        assert_eq!(&*same as *const _ as *const u8, ptr);
+
+        let larger = same.map_header(|single| (single, [0u64; 4]));
+        assert_eq!(larger.single, (-1, [0; 4]));
+        assert_eq!(&larger.many, &["a", "b"]);
+        assert_eq!(std::mem::size_of_val(&*larger), 88);
+
+        let counter = Rc::new(());
+        let value = DynStruct::new(0u8, &[Rc::clone(&counter), Rc::clone(&counter)]);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            value.map_header(|_| -> u8 { panic!("boom") })
+        }));
+        assert!(result.is_err());
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn map_tail() {
+        let value = DynStruct::new(String::from("tail"), &[1u32, 2, 3]);
+        let ptr = &*value as *const _ as *const u8;
+        let same = value.map_tail(|element| element as f32 / 2.0);
+        assert_eq!(same.single, "tail");
+        assert_eq!(&same.many, &[0.5, 1.0, 1.5]);
+        assert_eq!(&*same as *const _ as *const u8, ptr);
+
+        let strings = same.map_tail(|element| element.to_string());
+        assert_eq!(strings.single, "tail");
+        assert_eq!(&strings.many, &["0.5", "1", "1.5"]);
+
+        let bytes = strings.map_tail(|element| element.len() as u8);
+        assert_eq!(&bytes.many, &[3, 1, 3]);
+        assert_eq!(std::mem::size_of_val(&*bytes), 32);
+
+        fn explode<E>(map: impl Fn(Rc<()>) -> E) {
+            let counter = Rc::new(());
+            let value = DynStruct::from_vec(Rc::clone(&counter), vec![Rc::clone(&counter); 4]);
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                let mut count = 0;
+                value.map_tail(|element| {
+                    count += 1;
+                    assert!(count < 3, "boom");
+                    map(element)
+                })
+            }));
+            assert!(result.is_err());
+            assert_eq!(Rc::strong_count(&counter), 1);
+        }
+        explode(|element| element);
+        explode(|element| (element, [0u64; 2]));
+    }
+
+    #[test]
+    fn clone() {
+        let value = DynStruct::new(String::from("clone"), &[vec![1u8], vec![2, 3]]);
+        let clone = value.clone();
+        assert_eq!(clone, value);
+        assert_ne!(
+            &*clone as *const _ as *const u8,
+            &*value as *const _ as *const u8
+        );
+
+        let default = Box::<DynStruct<u32, String>>::default();
+        assert_eq!(default.single, 0);
+        assert!(default.many.is_empty());
+
+        let borrowed = std::borrow::Cow::Borrowed(&*value);
+        let mut owned = borrowed.clone();
+        owned.to_mut().many[0].push(4);
+        assert_eq!(&owned.many, &[vec![1, 4], vec![2, 3]]);
+        assert_eq!(&value.many, &[vec![1], vec![2, 3]]);
+    }
+
+    #[test]
+    fn collection() {
+        let mut value = DynStruct::new("collection", &[1u32, 2, 3]);
+        assert_eq!(value.len(), 3);
+        assert!(!value.is_empty());
+        assert!(DynStruct::new((), &[0u8; 0]).is_empty());
+
+        assert_eq!(value[2], 3);
+        assert_eq!(&value[..2], &[1, 2]);
+        value[0] = 4;
+
+        for element in &mut *value {
+            *element += 1;
+        }
+        assert_eq!((&*value).into_iter().collect::<Vec<_>>(), [&5, &3, &4]);
+
+        AsMut::<[u32]>::as_mut(&mut *value).sort();
+        assert_eq!(AsRef::<[u32]>::as_ref(&*value), &[3, 4, 5]);
+    }
+
+    #[test]
+    fn from_bytes() {
+        #[repr(C, align(8))]
+        struct Buffer([u8; 16]);
+
+        let mut buffer = Buffer([0; 16]);
+        buffer.0[..4].copy_from_slice(&7u32.to_ne_bytes());
+        buffer.0[4..6].copy_from_slice(&1u16.to_ne_bytes());
+        buffer.0[6..8].copy_from_slice(&2u16.to_ne_bytes());
+
+        let value = DynStruct::<u32, u16>::from_bytes(&buffer.0[..8]).unwrap();
+        assert_eq!(value.single, 7);
+        assert_eq!(&value.many, &[1, 2]);
+        let value = DynStruct::<u32, u16>::from_bytes(&buffer.0).unwrap();
+        assert_eq!(&value.many, &[1, 2, 0, 0, 0, 0]);
+
+        let value = DynStruct::<u32, u16>::from_bytes_mut(&mut buffer.0[..8]).unwrap();
+        value.single = 8;
+        value.many[1] = 3;
+        assert_eq!(&buffer.0[..4], &8u32.to_ne_bytes());
+        assert_eq!(&buffer.0[6..8], &3u16.to_ne_bytes());
+
+        // the size of a value always is a multiple of the alignment of `u32`
+        assert_eq!(
+            DynStruct::<u32, u16>::from_bytes(&buffer.0[..6]).unwrap_err(),
+            DynStructError::SizeMismatch(6)
+        );
+        assert_eq!(
+            DynStruct::<u64, u8>::from_bytes(&buffer.0[..4]).unwrap_err(),
+            DynStructError::BufferTooSmall(Layout::new::<u64>())
+        );
+        assert_eq!(
+            DynStruct::<u32, u8>::from_bytes(&buffer.0[1..13]).unwrap_err(),
+            DynStructError::MisalignedBuffer(Layout::new::<u32>())
+        );
+        assert_eq!(
+            DynStruct::<u32, ()>::from_bytes(&buffer.0[..4])
+                .unwrap()
+                .many
+                .len(),
+            0
+        );
+
+        let (value, rest) = DynStruct::<u32, u16>::from_bytes_prefix(&buffer.0, 3).unwrap();
+        assert_eq!(value.single, 8);
+        assert_eq!(&value.many, &[1, 3, 0]);
+        assert_eq!(rest.len(), 4);
+        assert_eq!(
+            DynStruct::<u32, u16>::from_bytes_prefix(&buffer.0, 7).unwrap_err(),
+            DynStructError::BufferTooSmall(Layout::from_size_align(20, 4).unwrap())
+        );
+    }
+
+    #[test]
+    fn as_bytes() {
+        let value = DynStruct::new(1u32, &[2u16, 3]);
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&1u32.to_ne_bytes());
+        bytes.extend_from_slice(&2u16.to_ne_bytes());
+        bytes.extend_from_slice(&3u16.to_ne_bytes());
+        assert_eq!(value.as_bytes(), bytes);
+
+        let value = DynStruct::new([1u8; 3], &[[2u8; 3]]);
+        assert_eq!(value.as_bytes(), &[1, 1, 1, 2, 2, 2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "padding")]
+    fn as_bytes_padding() {
+        DynStruct::new(1u32, &[2u16]).as_bytes();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn write_to() {
+        let value = DynStruct::new(1u32, &[2u16, 3, 4]);
+        let mut bytes = Vec::new();
+        value.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 8 + 12);
+        assert_eq!(&bytes[..8], &3u64.to_le_bytes());
+        assert_eq!(&bytes[18..], &[0, 0]);
+
+        let read = DynStruct::<u32, u16>::read_from(&bytes[..]).unwrap();
+        assert_eq!(read, value);
+
+        let error = DynStruct::<u32, u16>::read_from(&bytes[..19]).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+        let error = DynStruct::<u32, u16>::read_from(&[0xff; 8][..]).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(feature = "allocator-api2")]
+    #[test]
+    fn new_in() {
+        use allocator_api2::alloc::{AllocError, Allocator, Global};
+        use std::cell::Cell;
+
+        /// Keeps track of the number of bytes currently allocated.
+        #[derive(Default)]
+        struct Tracking(Cell<usize>);
+
+        unsafe impl Allocator for &Tracking {
+            fn allocate(&self, layout: Layout) -> Result<std::ptr::NonNull<[u8]>, AllocError> {
+                self.0.set(self.0.get() + layout.size());
+                Global.allocate(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: std::ptr::NonNull<u8>, layout: Layout) {
+                self.0.set(self.0.get() - layout.size());
+                Global.deallocate(ptr, layout)
+            }
+        }
+
+        let tracking = Tracking::default();
+        let value = DynStruct::new_in(String::from("header"), &[1u8, 2, 3], &tracking);
+        assert_eq!(value.single, "header");
+        assert_eq!(&value.many, &[1, 2, 3]);
+        assert_eq!(tracking.0.get(), std::mem::size_of_val(&*value));
+        drop(value);
+        assert_eq!(tracking.0.get(), 0);
+    }
+
     #[test]
     fn from_slice() {
         let same = DynStruct::<u32, u32>::from_slice(&[1, 2, 3]);
         assert_eq!(same.single, 1);
         assert_eq!(&same.many, &[2, 3]);
     }
+
+    #[test]
+    fn from_slice_mut() {
+        let mut values = [1u32, 2, 3];
+        let same = DynStruct::from_slice_mut(&mut values);
+        same.single = 4;
+        same.many[1] = 5;
+        assert_eq!(values, [4, 2, 5]);
+
+        assert!(DynStruct::<u8, u8>::try_from_slice(&[]).is_none());
+        let single = DynStruct::<u8, u8>::try_from_slice(&[1]).unwrap();
+        assert_eq!(single.single, 1);
+        assert!(single.many.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "without `single` value")]
+    fn from_slice_empty() {
+        DynStruct::<u32, u32>::from_slice(&[]);
+    }
+
+    #[test]
+    fn from_boxed_slice() {
+        let values: Box<[String]> = vec![String::from("a"), String::from("b")].into_boxed_slice();
+        let same = DynStruct::from_boxed_slice(values);
+        assert_eq!(same.single, "a");
+        assert_eq!(&same.many, &[String::from("b")]);
+        assert_eq!(
+            &*same.into_boxed_slice(),
+            &[String::from("a"), String::from("b")]
+        );
+    }
+
+    #[test]
+    fn from_prefixed_slice() {
+        let mut values = [1u16, 2, 3, 4, 5];
+        let prefixed = DynStruct::<[u16; 2], u16>::from_prefixed_slice(&values).unwrap();
+        assert_eq!(prefixed.single, [1, 2]);
+        assert_eq!(&prefixed.many, &[3, 4, 5]);
+        assert_eq!(std::mem::size_of_val(prefixed), 10);
+
+        let empty = DynStruct::<[u16; 5], u16>::from_prefixed_slice(&values).unwrap();
+        assert!(empty.many.is_empty());
+        assert!(DynStruct::<[u16; 6], u16>::from_prefixed_slice(&values).is_none());
+
+        let prefixed = DynStruct::<[u16; 3], u16>::from_prefixed_slice_mut(&mut values).unwrap();
+        prefixed.single[0] = 6;
+        prefixed.many[0] = 7;
+        assert_eq!(values, [6, 2, 3, 7, 5]);
+    }
 }
@@ -0,0 +1,163 @@
+use crate::raw;
+use crate::{Allocation, DynStruct};
+
+use alloc::boxed::Box;
+use core::ptr;
+
+impl<T, D> DynStruct<T, D> {
+    /// Borrow the `single` value and the elements at the same time.
+    pub fn parts(&self) -> (&T, &[D]) {
+        (&self.single, &self.many)
+    }
+
+    /// Mutably borrow the `single` value and the elements at the same time, so that the header
+    /// can be updated while iterating over the elements.
+    pub fn parts_mut(&mut self) -> (&mut T, &mut [D]) {
+        (&mut self.single, &mut self.many)
+    }
+
+    /// Replace the `single` value with the result of `f`, keeping the elements. The allocation is
+    /// reused if the new value has the same layout, otherwise the elements are moved into a new
+    /// allocation.
+    ///
+    /// If `f` panics, the elements are dropped.
+    pub fn map_header<U>(self: Box<Self>, f: impl FnOnce(T) -> U) -> Box<DynStruct<U, D>> {
+        let len = self.many.len();
+        let layout = raw::layout::<Self>(len);
+        let raw = Box::into_raw(self) as *mut u8;
+
+        let single = unsafe { (raw as *mut T).read() };
+        let mut guard = ElementsGuard {
+            _allocation: Allocation { raw, layout },
+            many: ptr::slice_from_raw_parts_mut(raw::many_ptr::<Self>(raw), len),
+        };
+        let single = f(single);
+
+        unsafe {
+            if same_layout::<Self, DynStruct<U, D>>() {
+                core::mem::forget(guard);
+                raw::write_header::<DynStruct<U, D>>(raw, single);
+                Box::from_raw(DynStruct::ptr_from_raw_parts(raw, len))
+            } else {
+                let target = Allocation::new(raw::layout::<DynStruct<U, D>>(len)).into_raw();
+                raw::write_header::<DynStruct<U, D>>(target, single);
+                raw::many_ptr::<DynStruct<U, D>>(target)
+                    .copy_from_nonoverlapping(raw::many_ptr::<Self>(raw), len);
+
+                // the elements have been moved: only free the old allocation
+                guard.many = ptr::slice_from_raw_parts_mut(raw::many_ptr::<Self>(raw), 0);
+                drop(guard);
+
+                Box::from_raw(DynStruct::ptr_from_raw_parts(target, len))
+            }
+        }
+    }
+
+    /// Replace each element with the result of calling `f` on it, keeping the `single` value. The
+    /// allocation is reused if the new value has the same layout, otherwise the value is moved
+    /// into a new allocation.
+    ///
+    /// If `f` panics, the `single` value and all elements are dropped.
+    pub fn map_tail<E>(self: Box<Self>, mut f: impl FnMut(D) -> E) -> Box<DynStruct<T, E>> {
+        let len = self.many.len();
+        let layout = raw::layout::<Self>(len);
+
+        let in_place = same_layout::<Self, DynStruct<T, E>>()
+            && core::mem::size_of::<D>() == core::mem::size_of::<E>();
+        let target = if in_place {
+            None
+        } else {
+            Some(Allocation::new(raw::layout::<DynStruct<T, E>>(len)))
+        };
+
+        let raw = Box::into_raw(self) as *mut u8;
+        let target_raw = target.as_ref().map_or(raw, |target| target.raw);
+        let mut guard = MapGuard {
+            source: Allocation { raw, layout },
+            target,
+            header: target_raw as *mut T,
+            from: raw::many_ptr::<Self>(raw),
+            to: raw::many_ptr::<DynStruct<T, E>>(target_raw),
+            len,
+            mapped: 0,
+        };
+
+        unsafe {
+            if !in_place {
+                let single = (raw as *mut T).read();
+                raw::write_header::<DynStruct<T, E>>(target_raw, single);
+            }
+
+            while guard.mapped < len {
+                let element = f(guard.from.add(guard.mapped).read());
+                guard.to.add(guard.mapped).write(element);
+                guard.mapped += 1;
+            }
+
+            // everything has been moved: only free the old allocation if it was not reused
+            let source = ptr::read(&guard.source);
+            let target = ptr::read(&guard.target);
+            core::mem::forget(guard);
+            match target {
+                Some(target) => {
+                    target.into_raw();
+                    drop(source);
+                }
+                None => core::mem::forget(source),
+            }
+
+            Box::from_raw(DynStruct::ptr_from_raw_parts(target_raw, len))
+        }
+    }
+}
+
+/// If values of `A` and `B` with the same number of elements have the same alignment and start
+/// their elements at the same offset.
+fn same_layout<A, B>() -> bool
+where
+    A: ?Sized + raw::RawDst,
+    B: ?Sized + raw::RawDst,
+{
+    A::TAIL_OFFSET == B::TAIL_OFFSET && raw::align::<A>() == raw::align::<B>()
+}
+
+/// The elements of a value whose header has been moved out, which are dropped together with the
+/// allocation.
+struct ElementsGuard<D> {
+    _allocation: Allocation,
+    many: *mut [D],
+}
+
+impl<D> Drop for ElementsGuard<D> {
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(self.many) }
+    }
+}
+
+/// A value whose elements are being mapped from `D` to `E`, which is cleaned up after a panic.
+///
+/// The elements before `mapped` have been written to `to`, and the elements after it are still
+/// in `from`. The element at `mapped` has been moved into the closure.
+struct MapGuard<T, D, E> {
+    source: Allocation,
+    target: Option<Allocation>,
+    header: *mut T,
+    from: *mut D,
+    to: *mut E,
+    len: usize,
+    mapped: usize,
+}
+
+impl<T, D, E> Drop for MapGuard<T, D, E> {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(self.header);
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.to, self.mapped));
+            let rest = self.from.add(self.mapped + 1);
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(
+                rest,
+                self.len - self.mapped - 1,
+            ));
+        }
+    }
+}
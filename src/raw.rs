@@ -0,0 +1,413 @@
+use crate::vec::RawVec;
+use crate::{Allocation, DynStructError, SliceDst};
+
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+#[cfg(target_has_atomic = "ptr")]
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::alloc::Layout;
+use core::mem::{align_of, size_of, ManuallyDrop};
+
+/// A `#[repr(C)]` dynamically sized type, which starts with the fields of a header and ends in a
+/// slice. This is the layout shared by [`DynStruct`](struct@crate::DynStruct) and the types using
+/// the `DynStruct` derive macro, which lets them share the code that builds their values.
+///
+/// # Safety
+///
+/// The fields of `Header` have to be at the same offsets as the fields in front of the slice, and
+/// the slice has to start at `TAIL_OFFSET`. The alignment of `Self` has to be the larger one of
+/// the alignments of `Header` and `Element`.
+pub unsafe trait RawDst: SliceDst {
+    /// A struct with the fields in front of the slice.
+    type Header;
+    /// The type of the elements in the slice.
+    type Element;
+    /// The offset of the slice from the start of the value. Note that this may be smaller than
+    /// the size of `Header`, which is padded to its own alignment.
+    const TAIL_OFFSET: usize;
+}
+
+/// The layout of a value with `len` elements of `element_size` bytes, which start at
+/// `tail_offset`. This matches what the compiler uses for `#[repr(C)]` structs, including the
+/// padding at the end.
+pub const fn layout_for(
+    tail_offset: usize,
+    align: usize,
+    element_size: usize,
+    len: usize,
+) -> Result<Layout, DynStructError> {
+    if !align.is_power_of_two() {
+        return Err(DynStructError::InvalidAlignment);
+    }
+
+    let size = match element_size.checked_mul(len) {
+        Some(many) => match tail_offset.checked_add(many) {
+            Some(size) => round_up(size, align),
+            None => None,
+        },
+        None => None,
+    };
+
+    match size {
+        Some(size) => match Layout::from_size_align(size, align) {
+            Ok(layout) => Ok(layout),
+            Err(_) => Err(DynStructError::LayoutOverflow),
+        },
+        None => Err(DynStructError::LayoutOverflow),
+    }
+}
+
+/// The largest number of elements for which [`layout_for`] succeeds.
+pub const fn max_len(tail_offset: usize, align: usize, element_size: usize) -> usize {
+    match element_size {
+        0 => usize::MAX,
+        element => {
+            // the largest size which is a multiple of `align` and at most `isize::MAX`
+            let max_size = isize::MAX as usize - (align - 1);
+            match max_size.checked_sub(tail_offset) {
+                Some(max_many) => max_many / element,
+                None => 0,
+            }
+        }
+    }
+}
+
+/// The offset of a slice of elements with the alignment `element_align`, which follows fields
+/// ending at `header_end`.
+pub const fn tail_offset(header_end: usize, element_align: usize) -> usize {
+    // `header_end` is at most `isize::MAX`, so rounding it up cannot overflow
+    match round_up(header_end, element_align) {
+        Some(offset) => offset,
+        None => panic!("the size of the header overflowed"),
+    }
+}
+
+/// The larger one of two alignments.
+pub const fn max_align(a: usize, b: usize) -> usize {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+/// Round `value` up to the nearest multiple of `align`, which has to be a power of two.
+const fn round_up(value: usize, align: usize) -> Option<usize> {
+    match value.checked_add(align - 1) {
+        Some(value) => Some(value & !(align - 1)),
+        None => None,
+    }
+}
+
+/// The alignment of `S`.
+pub(crate) fn align<S: ?Sized + RawDst>() -> usize {
+    max_align(align_of::<S::Header>(), align_of::<S::Element>())
+}
+
+/// The layout of a value of `S` with `len` elements.
+pub(crate) fn try_layout<S: ?Sized + RawDst>(len: usize) -> Result<Layout, DynStructError> {
+    layout_for(S::TAIL_OFFSET, align::<S>(), size_of::<S::Element>(), len)
+}
+
+/// Same as [`try_layout`], but panics if the layout is invalid.
+pub(crate) fn layout<S: ?Sized + RawDst>(len: usize) -> Layout {
+    try_layout::<S>(len).unwrap_or_else(|error| panic!("{}", error))
+}
+
+/// Create a fat pointer to a value of `S` at `raw` with `len` elements.
+pub(crate) fn fat_ptr<S: ?Sized + RawDst>(raw: *mut u8, len: usize) -> *mut S {
+    S::ptr_from_raw_parts(raw, len)
+}
+
+/// Same as [`DynStruct::into_raw_parts`](crate::DynStruct::into_raw_parts), but for any
+/// `SliceDst`.
+pub fn into_raw_parts<S: ?Sized + SliceDst>(boxed: Box<S>) -> (*mut u8, usize) {
+    let len = S::ptr_len(&*boxed);
+    (Box::into_raw(boxed) as *mut u8, len)
+}
+
+/// # Safety
+///
+/// `data` and `len` have to be returned by [`into_raw_parts`] for a `Box<S>`.
+pub unsafe fn from_raw_parts<S: ?Sized + SliceDst>(data: *mut u8, len: usize) -> Box<S> {
+    Box::from_raw(S::ptr_from_raw_parts(data, len))
+}
+
+pub(crate) fn many_ptr<S: ?Sized + RawDst>(raw: *mut u8) -> *mut S::Element {
+    unsafe { raw.add(S::TAIL_OFFSET) as *mut S::Element }
+}
+
+/// Write `header` to the start of a value of `S` at `raw`.
+///
+/// The trailing padding of `header` may overlap the elements, so only the bytes in front of them
+/// are written. This leaves any elements intact, no matter if they were written before or after.
+///
+/// # Safety
+///
+/// `raw` must be valid for writes of a value of `S`, and properly aligned.
+pub(crate) unsafe fn write_header<S: ?Sized + RawDst>(raw: *mut u8, header: S::Header) {
+    let header = ManuallyDrop::new(header);
+    let size = usize::min(size_of::<S::Header>(), S::TAIL_OFFSET);
+    (&*header as *const S::Header as *const u8).copy_to_nonoverlapping(raw, size);
+}
+
+/// Writes `header` and `len` elements produced by `element` into the memory pointed to by `raw`.
+/// If `element` panics, everything written so far is dropped again.
+///
+/// # Safety
+///
+/// `raw` must be valid for writes of a value of `S` with `len` elements, and properly aligned.
+pub(crate) unsafe fn init_with<S: ?Sized + RawDst>(
+    raw: *mut u8,
+    header: S::Header,
+    len: usize,
+    mut element: impl FnMut(usize) -> S::Element,
+) {
+    write_header::<S>(raw, header);
+
+    let mut guard = InitGuard {
+        header: raw as *mut S::Header,
+        many: many_ptr::<S>(raw),
+        len: 0,
+    };
+    while guard.len < len {
+        guard.many.add(guard.len).write(element(guard.len));
+        guard.len += 1;
+    }
+    core::mem::forget(guard);
+}
+
+/// The number of `Chunk`s that make up a value of `S` with `len` elements.
+fn chunk_count<S: ?Sized + RawDst>(len: usize) -> usize {
+    let layout = layout::<S>(len);
+    layout.size() / layout.align()
+}
+
+pub fn new_with<S: ?Sized + RawDst>(
+    header: S::Header,
+    len: usize,
+    element: impl FnMut(usize) -> S::Element,
+) -> Box<S> {
+    let allocation = Allocation::new(layout::<S>(len));
+    unsafe {
+        init_with::<S>(allocation.raw, header, len, element);
+        Box::from_raw(fat_ptr(allocation.into_raw(), len))
+    }
+}
+
+pub fn new<S: ?Sized + RawDst>(header: S::Header, many: &[S::Element]) -> Box<S>
+where
+    S::Element: Clone,
+{
+    new_with(header, many.len(), |i| many[i].clone())
+}
+
+pub fn try_new<S: ?Sized + RawDst>(
+    header: S::Header,
+    many: &[S::Element],
+) -> Result<Box<S>, DynStructError>
+where
+    S::Element: Clone,
+{
+    let allocation = Allocation::try_new(try_layout::<S>(many.len())?)?;
+    unsafe {
+        init_with::<S>(allocation.raw, header, many.len(), |i| many[i].clone());
+        Ok(Box::from_raw(fat_ptr(allocation.into_raw(), many.len())))
+    }
+}
+
+/// # Safety
+///
+/// The all-zero bit pattern has to be valid for `S::Header` and `S::Element`.
+pub unsafe fn new_zeroed<S: ?Sized + RawDst>(len: usize) -> Box<S> {
+    let allocation = Allocation::new_zeroed(layout::<S>(len));
+    Box::from_raw(fat_ptr(allocation.into_raw(), len))
+}
+
+/// # Safety
+///
+/// The all-zero bit pattern has to be valid for `S::Element`.
+pub unsafe fn new_zeroed_with_header<S: ?Sized + RawDst>(header: S::Header, len: usize) -> Box<S> {
+    let allocation = Allocation::new_zeroed(layout::<S>(len));
+    write_header::<S>(allocation.raw, header);
+    Box::from_raw(fat_ptr(allocation.into_raw(), len))
+}
+
+pub fn from_vec<S: ?Sized + RawDst>(header: S::Header, mut many: Vec<S::Element>) -> Box<S> {
+    let allocation = Allocation::new(layout::<S>(many.len()));
+    unsafe {
+        write_header::<S>(allocation.raw, header);
+        many_ptr::<S>(allocation.raw).copy_from_nonoverlapping(many.as_ptr(), many.len());
+
+        // the elements have been moved into the new allocation: only free the buffer
+        let len = many.len();
+        many.set_len(0);
+
+        Box::from_raw(fat_ptr(allocation.into_raw(), len))
+    }
+}
+
+pub fn from_iter_exact<S: ?Sized + RawDst, I>(header: S::Header, many: I) -> Box<S>
+where
+    I: IntoIterator<Item = S::Element>,
+    I::IntoIter: ExactSizeIterator,
+{
+    from_iter_exact_with(many, |_| header)
+}
+
+/// Same as [`from_iter_exact`], but the header is created from the number of elements by
+/// `header`, once all of them have been written.
+pub fn from_iter_exact_with<S: ?Sized + RawDst, I>(
+    many: I,
+    header: impl FnOnce(usize) -> S::Header,
+) -> Box<S>
+where
+    I: IntoIterator<Item = S::Element>,
+    I::IntoIter: ExactSizeIterator,
+{
+    let many = many.into_iter();
+    let mut vec = RawVec::<S>::without_header(many.len());
+    vec.extend(many);
+    vec.write_header(header);
+    vec.into_box()
+}
+
+pub fn from_iter<S: ?Sized + RawDst, I>(header: S::Header, many: I) -> Box<S>
+where
+    I: IntoIterator<Item = S::Element>,
+{
+    from_iter_with(many, |_| header)
+}
+
+/// Same as [`from_iter`], but the header is created from the number of elements by `header`,
+/// once all of them have been written.
+pub fn from_iter_with<S: ?Sized + RawDst, I>(
+    many: I,
+    header: impl FnOnce(usize) -> S::Header,
+) -> Box<S>
+where
+    I: IntoIterator<Item = S::Element>,
+{
+    let mut vec = RawVec::<S>::without_header(0);
+    vec.extend(many);
+    vec.write_header(header);
+    vec.into_box()
+}
+
+pub fn new_rc<S: ?Sized + RawDst>(header: S::Header, many: &[S::Element]) -> Rc<S>
+where
+    S::Element: Clone,
+{
+    let mut chunks =
+        Rc::<[Chunk<S::Header, S::Element>]>::new_uninit_slice(chunk_count::<S>(many.len()));
+
+    // the `Rc` was just created, so we are guaranteed to have unique access
+    let raw = Rc::get_mut(&mut chunks).unwrap().as_mut_ptr() as *mut u8;
+    unsafe {
+        init_with::<S>(raw, header, many.len(), |i| many[i].clone());
+        let raw = Rc::into_raw(chunks) as *mut u8;
+        Rc::from_raw(fat_ptr::<S>(raw, many.len()))
+    }
+}
+
+#[cfg(target_has_atomic = "ptr")]
+pub fn new_arc<S: ?Sized + RawDst>(header: S::Header, many: &[S::Element]) -> Arc<S>
+where
+    S::Element: Clone,
+{
+    let mut chunks =
+        Arc::<[Chunk<S::Header, S::Element>]>::new_uninit_slice(chunk_count::<S>(many.len()));
+
+    // the `Arc` was just created, so we are guaranteed to have unique access
+    let raw = Arc::get_mut(&mut chunks).unwrap().as_mut_ptr() as *mut u8;
+    unsafe {
+        init_with::<S>(raw, header, many.len(), |i| many[i].clone());
+        let raw = Arc::into_raw(chunks) as *mut u8;
+        Arc::from_raw(fat_ptr::<S>(raw, many.len()))
+    }
+}
+
+/// View the start of `bytes` as a value of `S` with `len` elements. Returns the value and the
+/// remaining bytes after it.
+///
+/// # Safety
+///
+/// The bytes of the value have to be valid values of `S::Header` and `S::Element`.
+pub(crate) unsafe fn from_bytes_prefix<S: ?Sized + RawDst>(
+    bytes: &[u8],
+    len: usize,
+) -> Result<(&S, &[u8]), DynStructError> {
+    let layout = try_layout::<S>(len)?;
+    if bytes.as_ptr().align_offset(layout.align()) != 0 {
+        return Err(DynStructError::MisalignedBuffer(layout));
+    }
+    if bytes.len() < layout.size() {
+        return Err(DynStructError::BufferTooSmall(layout));
+    }
+
+    let (value, rest) = bytes.split_at(layout.size());
+    Ok((&*fat_ptr(value.as_ptr() as *mut u8, len), rest))
+}
+
+/// View the start of `bytes` as a value of `S`, with the number of elements returned by `len`
+/// for its header.
+///
+/// # Safety
+///
+/// Every bit pattern has to be a valid value of `S::Header` and `S::Element`.
+pub unsafe fn from_bytes_with_len<S: ?Sized + RawDst>(
+    bytes: &[u8],
+    len: impl FnOnce(&S::Header) -> Result<usize, DynStructError>,
+) -> Result<(&S, &[u8]), DynStructError> {
+    // a value without elements is at least as large as its header
+    from_bytes_prefix::<S>(bytes, 0)?;
+    let len = len(&*(bytes.as_ptr() as *const S::Header))?;
+    from_bytes_prefix(bytes, len)
+}
+
+/// View `value` as bytes, including the header and all elements.
+///
+/// Panics if the value contains padding bytes.
+///
+/// # Safety
+///
+/// `S::Header` and `S::Element` must not contain any padding bytes themselves.
+pub unsafe fn as_bytes<S: ?Sized + RawDst>(value: &S) -> &[u8] {
+    let size = core::mem::size_of_val(value);
+    let many = size_of::<S::Element>() * S::ptr_len(value);
+    assert!(
+        S::TAIL_OFFSET == size_of::<S::Header>() && size == S::TAIL_OFFSET + many,
+        "`{}` contains padding bytes",
+        core::any::type_name::<S>()
+    );
+
+    core::slice::from_raw_parts(value as *const S as *const u8, size)
+}
+
+/// Drops the header and the first `len` elements of a value under construction. Used to clean up
+/// after a panic.
+struct InitGuard<H, D> {
+    header: *mut H,
+    many: *mut D,
+    len: usize,
+}
+
+impl<H, D> Drop for InitGuard<H, D> {
+    fn drop(&mut self) {
+        unsafe {
+            core::ptr::drop_in_place(self.header);
+            core::ptr::drop_in_place(core::ptr::slice_from_raw_parts_mut(self.many, self.len));
+        }
+    }
+}
+
+/// A placeholder with the same alignment as a value with a header `H` and elements `D`, and a
+/// size equal to that alignment. A slice of these can have the same layout as such a value, which
+/// lets us borrow the allocation logic of `Rc<[_]>` and `Arc<[_]>`.
+#[repr(C)]
+struct Chunk<H, D> {
+    _header: [H; 0],
+    _many: [D; 0],
+    _byte: u8,
+}
@@ -0,0 +1,397 @@
+use crate::raw::{self, RawDst};
+use crate::{Allocation, DynStruct, SliceDst};
+
+use alloc::boxed::Box;
+use core::alloc::Layout;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::size_of;
+use core::ops::{Deref, DerefMut};
+use core::ptr::NonNull;
+#[cfg(target_has_atomic = "ptr")]
+use core::sync::atomic::{self, AtomicUsize, Ordering};
+
+/// A `ThinBox` holding a [`DynStruct`](struct@crate::DynStruct).
+pub type ThinDynStruct<T, D> = ThinBox<DynStruct<T, D>>;
+
+/// A `ThinArc` holding a [`DynStruct`](struct@crate::DynStruct).
+#[cfg(target_has_atomic = "ptr")]
+pub type ThinArcDynStruct<T, D> = ThinArc<DynStruct<T, D>>;
+
+/// An owning pointer to a dynamically sized value, like `Box<S>`, but only one word wide.
+///
+/// Instead of being part of the pointer, the length of the trailing slice is stored in the same
+/// allocation as the value, right in front of it.
+///
+/// ```
+/// use dyn_struct::{DynStruct, ThinDynStruct};
+///
+/// let thin = ThinDynStruct::new(7u32, &[1u8, 2, 3]);
+/// assert_eq!(thin.single, 7);
+/// assert_eq!(&thin.many, &[1, 2, 3]);
+/// assert_eq!(std::mem::size_of_val(&thin), std::mem::size_of::<usize>());
+/// ```
+pub struct ThinBox<S: ?Sized + SliceDst> {
+    /// Points to the value, which is directly preceded by its length.
+    ptr: NonNull<u8>,
+    _marker: PhantomData<S>,
+}
+
+unsafe impl<S: ?Sized + SliceDst + Send> Send for ThinBox<S> {}
+unsafe impl<S: ?Sized + SliceDst + Sync> Sync for ThinBox<S> {}
+
+impl<T, D> ThinBox<DynStruct<T, D>> {
+    /// Same as [`DynStruct::new`], but returns a `ThinBox`.
+    pub fn new(single: T, many: &[D]) -> Self
+    where
+        D: Clone,
+    {
+        new_thin(single, many)
+    }
+}
+
+/// Same as [`DynStruct::new`], but returns a `ThinBox` to any `RawDst`.
+pub fn new_thin<S: ?Sized + RawDst>(header: S::Header, many: &[S::Element]) -> ThinBox<S>
+where
+    S::Element: Clone,
+{
+    ThinBox {
+        ptr: new_with_prefix::<usize, S>(many.len(), header, many),
+        _marker: PhantomData,
+    }
+}
+
+impl<S: ?Sized + SliceDst> ThinBox<S> {
+    /// Move a value out of a `Box` into a `ThinBox`.
+    pub fn from_box(boxed: Box<S>) -> Self {
+        ThinBox {
+            ptr: from_box_with_prefix(S::ptr_len(&*boxed), boxed),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Reinterpret a `ThinBox<S>` as a `ThinBox<U>`.
+    ///
+    /// # Safety
+    ///
+    /// A value of `S` has to be a valid value of `U` with the same length, and both need to have
+    /// the same layout for every length.
+    pub unsafe fn cast<U: ?Sized + SliceDst>(this: Self) -> ThinBox<U> {
+        let ptr = this.ptr;
+        core::mem::forget(this);
+        ThinBox {
+            ptr,
+            _marker: PhantomData,
+        }
+    }
+
+    fn as_ptr(&self) -> *mut S {
+        unsafe { S::ptr_from_raw_parts(self.ptr.as_ptr(), len(self.ptr)) }
+    }
+}
+
+impl<S: ?Sized + SliceDst> Deref for ThinBox<S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        unsafe { &*self.as_ptr() }
+    }
+}
+
+impl<S: ?Sized + SliceDst> DerefMut for ThinBox<S> {
+    fn deref_mut(&mut self) -> &mut S {
+        unsafe { &mut *self.as_ptr() }
+    }
+}
+
+impl<S: ?Sized + SliceDst> Drop for ThinBox<S> {
+    fn drop(&mut self) {
+        unsafe { drop_with_prefix::<usize, S>(self.as_ptr()) }
+    }
+}
+
+impl<S: ?Sized + RawDst> Clone for ThinBox<S>
+where
+    S::Header: Clone,
+    S::Element: Clone,
+{
+    fn clone(&self) -> Self {
+        let raw = self.ptr.as_ptr();
+        unsafe {
+            // the header is at the start of the value, followed by the elements
+            let header = &*(raw as *const S::Header);
+            let many = core::slice::from_raw_parts(raw::many_ptr::<S>(raw), len(self.ptr));
+            new_thin::<S>(header.clone(), many)
+        }
+    }
+}
+
+impl<S: ?Sized + SliceDst + fmt::Debug> fmt::Debug for ThinBox<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        S::fmt(self, f)
+    }
+}
+
+/// A thread-safe reference-counted pointer to a dynamically sized value, like `Arc<S>`, but only
+/// one word wide.
+///
+/// Both the reference count and the length of the trailing slice are stored in the same
+/// allocation as the value, right in front of it. Unlike `Arc`, there are no weak references, so
+/// cloning and dropping only ever touch a single counter.
+///
+/// ```
+/// use dyn_struct::{DynStruct, ThinArcDynStruct};
+///
+/// let thin = ThinArcDynStruct::new(7u32, &[1u8, 2, 3]);
+/// let clone = thin.clone();
+/// assert_eq!(clone.single, 7);
+/// assert_eq!(&clone.many, &[1, 2, 3]);
+/// assert_eq!(std::mem::size_of_val(&clone), std::mem::size_of::<usize>());
+/// ```
+#[cfg(target_has_atomic = "ptr")]
+pub struct ThinArc<S: ?Sized + SliceDst> {
+    /// Points to the value, which is directly preceded by an `ArcPrefix`.
+    ptr: NonNull<u8>,
+    _marker: PhantomData<S>,
+}
+
+/// Stored in front of the value of a `ThinArc`. The length comes last, so that it is located at
+/// the same place as in a `ThinBox`.
+#[cfg(target_has_atomic = "ptr")]
+#[repr(C)]
+struct ArcPrefix {
+    count: AtomicUsize,
+    len: usize,
+}
+
+/// A limit on the reference count, to avoid overflowing it with `mem::forget`ed clones.
+#[cfg(target_has_atomic = "ptr")]
+const MAX_REFCOUNT: usize = isize::MAX as usize;
+
+#[cfg(target_has_atomic = "ptr")]
+unsafe impl<S: ?Sized + SliceDst + Send + Sync> Send for ThinArc<S> {}
+#[cfg(target_has_atomic = "ptr")]
+unsafe impl<S: ?Sized + SliceDst + Send + Sync> Sync for ThinArc<S> {}
+
+#[cfg(target_has_atomic = "ptr")]
+impl<T, D> ThinArc<DynStruct<T, D>> {
+    /// Same as [`DynStruct::new`], but returns a `ThinArc`.
+    pub fn new(single: T, many: &[D]) -> Self
+    where
+        D: Clone,
+    {
+        new_thin_arc(single, many)
+    }
+}
+
+/// Same as [`DynStruct::new`], but returns a `ThinArc` to any `RawDst`.
+#[cfg(target_has_atomic = "ptr")]
+pub fn new_thin_arc<S: ?Sized + RawDst>(header: S::Header, many: &[S::Element]) -> ThinArc<S>
+where
+    S::Element: Clone,
+{
+    let prefix = ArcPrefix {
+        count: AtomicUsize::new(1),
+        len: many.len(),
+    };
+    ThinArc {
+        ptr: new_with_prefix::<ArcPrefix, S>(prefix, header, many),
+        _marker: PhantomData,
+    }
+}
+
+#[cfg(target_has_atomic = "ptr")]
+impl<S: ?Sized + SliceDst> ThinArc<S> {
+    /// Move a value out of a `Box` into a `ThinArc`.
+    pub fn from_box(boxed: Box<S>) -> Self {
+        let prefix = ArcPrefix {
+            count: AtomicUsize::new(1),
+            len: S::ptr_len(&*boxed),
+        };
+        ThinArc {
+            ptr: from_box_with_prefix(prefix, boxed),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Reinterpret a `ThinArc<S>` as a `ThinArc<U>`.
+    ///
+    /// # Safety
+    ///
+    /// A value of `S` has to be a valid value of `U` with the same length, and both need to have
+    /// the same layout for every length.
+    pub unsafe fn cast<U: ?Sized + SliceDst>(this: Self) -> ThinArc<U> {
+        let ptr = this.ptr;
+        core::mem::forget(this);
+        ThinArc {
+            ptr,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The number of `ThinArc`s pointing to this value.
+    pub fn strong_count(this: &Self) -> usize {
+        this.prefix().count.load(Ordering::Acquire)
+    }
+
+    /// Returns `true` if both `ThinArc`s point to the same value.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.ptr == other.ptr
+    }
+
+    /// Returns a mutable reference to the value, if there are no other `ThinArc`s pointing to it.
+    pub fn get_mut(this: &mut Self) -> Option<&mut S> {
+        if this.prefix().count.load(Ordering::Acquire) == 1 {
+            Some(unsafe { &mut *this.as_ptr() })
+        } else {
+            None
+        }
+    }
+
+    fn prefix(&self) -> &ArcPrefix {
+        unsafe { &*(self.ptr.as_ptr() as *const ArcPrefix).sub(1) }
+    }
+
+    fn as_ptr(&self) -> *mut S {
+        unsafe { S::ptr_from_raw_parts(self.ptr.as_ptr(), len(self.ptr)) }
+    }
+}
+
+#[cfg(target_has_atomic = "ptr")]
+impl<S: ?Sized + SliceDst> Deref for ThinArc<S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        unsafe { &*self.as_ptr() }
+    }
+}
+
+#[cfg(target_has_atomic = "ptr")]
+impl<S: ?Sized + SliceDst> Clone for ThinArc<S> {
+    fn clone(&self) -> Self {
+        // Same as `Arc`: creating a new reference only requires that there already is one.
+        let old = self.prefix().count.fetch_add(1, Ordering::Relaxed);
+        if old > MAX_REFCOUNT {
+            abort();
+        }
+
+        ThinArc {
+            ptr: self.ptr,
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(target_has_atomic = "ptr")]
+impl<S: ?Sized + SliceDst> Drop for ThinArc<S> {
+    fn drop(&mut self) {
+        // Same as `Arc`: all uses of the value have to happen before it is dropped.
+        if self.prefix().count.fetch_sub(1, Ordering::Release) != 1 {
+            return;
+        }
+        atomic::fence(Ordering::Acquire);
+
+        unsafe { drop_with_prefix::<ArcPrefix, S>(self.as_ptr()) }
+    }
+}
+
+#[cfg(target_has_atomic = "ptr")]
+impl<S: ?Sized + SliceDst + fmt::Debug> fmt::Debug for ThinArc<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        S::fmt(self, f)
+    }
+}
+
+/// Aborts the process, even without `std`, by panicking while panicking.
+#[cfg(target_has_atomic = "ptr")]
+#[cold]
+fn abort() -> ! {
+    struct Abort;
+
+    impl Drop for Abort {
+        fn drop(&mut self) {
+            panic!("reference count overflow");
+        }
+    }
+
+    let _abort = Abort;
+    panic!("reference count overflow");
+}
+
+/// Allocate a `P` followed by a value of `S`, and return a pointer to the latter.
+fn new_with_prefix<P, S: ?Sized + RawDst>(
+    prefix: P,
+    header: S::Header,
+    many: &[S::Element],
+) -> NonNull<u8>
+where
+    S::Element: Clone,
+{
+    let (layout, offset) = layout_with_prefix::<P>(raw::layout::<S>(many.len()));
+    let allocation = Allocation::new(layout);
+    unsafe {
+        let ptr = allocation.raw.add(offset);
+        raw::init_with::<S>(ptr, header, many.len(), |i| many[i].clone());
+        (ptr as *mut P).sub(1).write(prefix);
+
+        // the value is complete: hand over the allocation
+        core::mem::forget(allocation);
+        NonNull::new_unchecked(ptr)
+    }
+}
+
+/// Allocate a `P` followed by the value in `boxed`, and return a pointer to the latter.
+pub(crate) fn from_box_with_prefix<P, S: ?Sized>(prefix: P, boxed: Box<S>) -> NonNull<u8> {
+    let value = Layout::for_value(&*boxed);
+    let (layout, offset) = layout_with_prefix::<P>(value);
+
+    let raw = Box::into_raw(boxed) as *mut u8;
+    unsafe {
+        let ptr = Allocation::new(layout).into_raw().add(offset);
+        ptr.copy_from_nonoverlapping(raw, value.size());
+        (ptr as *mut P).sub(1).write(prefix);
+
+        // the value has been moved: only free the memory
+        drop(Allocation { raw, layout: value });
+
+        NonNull::new_unchecked(ptr)
+    }
+}
+
+/// Drop the `P` and the value which `ptr` points to, and free their allocation.
+///
+/// # Safety
+///
+/// `ptr` must have been created by `new_with_prefix` or `from_box_with_prefix` with the same `P`.
+unsafe fn drop_with_prefix<P, S: ?Sized>(ptr: *mut S) {
+    let (layout, offset) = layout_with_prefix::<P>(Layout::for_value(&*ptr));
+    let raw = ptr as *mut u8;
+
+    core::ptr::drop_in_place(ptr);
+    core::ptr::drop_in_place((raw as *mut P).sub(1));
+    drop(Allocation {
+        raw: raw.sub(offset),
+        layout,
+    });
+}
+
+/// The length of the value `ptr` points to, which is stored right in front of it.
+///
+/// # Safety
+///
+/// `ptr` must have been created by `new_with_prefix` or `from_box_with_prefix`, with a `P` that
+/// ends in the length.
+unsafe fn len(ptr: NonNull<u8>) -> usize {
+    (ptr.as_ptr() as *const usize).sub(1).read()
+}
+
+/// The layout of an allocation with a `P` followed by a value with the layout `value`, and the
+/// offset of the value in it. The `P` is stored right in front of the value, which places it
+/// at a fixed offset from the value regardless of its alignment.
+pub(crate) fn layout_with_prefix<P>(value: Layout) -> (Layout, usize) {
+    let (layout, offset) = Layout::new::<P>()
+        .extend(value)
+        .unwrap_or_else(|_| panic!("{}", crate::DynStructError::LayoutOverflow));
+    debug_assert!(offset >= size_of::<P>());
+    (layout.pad_to_align(), offset)
+}
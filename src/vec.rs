@@ -0,0 +1,503 @@
+use crate::raw::{self, RawDst};
+use crate::thin;
+use crate::{Allocation, DynStruct};
+
+use alloc::boxed::Box;
+use core::alloc::Layout;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::ptr::NonNull;
+
+/// A growable [`DynStruct`](struct@crate::DynStruct), like a `Vec` with a header.
+///
+/// The `single` value and the elements live in a single allocation, with room for `capacity`
+/// elements. The number of elements and the capacity are stored in the same allocation, right in
+/// front of the value, so a `DynVec` is only one word wide. The allocation is grown in place with
+/// `realloc` when needed. Once done, the value can be turned into a `Box<DynStruct<T, D>>`.
+///
+/// ```
+/// use dyn_struct::{DynStruct, DynVec};
+///
+/// let mut vec = DynVec::new("numbers");
+/// vec.push(1);
+/// vec.extend_from_slice(&[2, 3]);
+/// assert_eq!(vec.single, "numbers");
+/// assert_eq!(&vec.many, &[1, 2, 3]);
+/// assert_eq!(std::mem::size_of_val(&vec), std::mem::size_of::<usize>());
+///
+/// let boxed: Box<DynStruct<&str, i32>> = vec.into_box();
+/// assert_eq!(&boxed.many, &[1, 2, 3]);
+/// ```
+pub struct DynVec<T, D> {
+    /// Points to the value, which is directly preceded by its `Lengths`.
+    ptr: NonNull<u8>,
+    _marker: PhantomData<Box<DynStruct<T, D>>>,
+}
+
+/// The number of elements of a `DynVec`, and how many there is room for.
+struct Lengths {
+    len: usize,
+    capacity: usize,
+}
+
+unsafe impl<T: Send, D: Send> Send for DynVec<T, D> {}
+unsafe impl<T: Sync, D: Sync> Sync for DynVec<T, D> {}
+
+impl<T, D> DynVec<T, D> {
+    /// Create a new `DynVec` with the given `single` value and no elements. This does not
+    /// allocate room for any elements.
+    pub fn new(single: T) -> Self {
+        DynVec::with_capacity(single, 0)
+    }
+
+    /// Create a new `DynVec` with the given `single` value and room for `capacity` elements.
+    pub fn with_capacity(single: T, capacity: usize) -> Self {
+        let capacity = Self::fit_capacity(capacity);
+        let (layout, offset) = Self::layout(capacity);
+        unsafe {
+            let ptr = Allocation::new(layout).into_raw().add(offset);
+            raw::write_header::<DynStruct<T, D>>(ptr, single);
+            (ptr as *mut Lengths)
+                .sub(1)
+                .write(Lengths { len: 0, capacity });
+            DynVec {
+                ptr: NonNull::new_unchecked(ptr),
+                _marker: PhantomData,
+            }
+        }
+    }
+
+    /// Move the value out of `boxed` into a `DynVec`, with room for exactly its current elements.
+    /// Since the lengths are stored in front of the value, this copies the value into a new
+    /// allocation.
+    pub fn from_box(boxed: Box<DynStruct<T, D>>) -> Self {
+        let len = boxed.many.len();
+        let lengths = Lengths {
+            len,
+            capacity: Self::fit_capacity(len),
+        };
+        DynVec {
+            ptr: thin::from_box_with_prefix(lengths, boxed),
+            _marker: PhantomData,
+        }
+    }
+
+    /// The number of elements.
+    pub fn len(&self) -> usize {
+        self.lengths().len
+    }
+
+    /// Returns `true` if there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of elements there is room for without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.lengths().capacity
+    }
+
+    /// Append an element.
+    pub fn push(&mut self, element: D) {
+        let len = self.len();
+        if len == self.capacity() {
+            self.reserve(1);
+        }
+
+        unsafe { self.many_ptr().add(len).write(element) };
+        self.lengths_mut().len = len + 1;
+    }
+
+    /// Append a clone of each element in `elements`.
+    pub fn extend_from_slice(&mut self, elements: &[D])
+    where
+        D: Clone,
+    {
+        self.reserve(elements.len());
+        for element in elements {
+            self.push(element.clone());
+        }
+    }
+
+    /// Make room for at least `additional` more elements. Like `Vec`, this may reserve more than
+    /// that, to avoid frequent reallocations.
+    pub fn reserve(&mut self, additional: usize) {
+        let required = self
+            .len()
+            .checked_add(additional)
+            .unwrap_or_else(|| panic!("{}", crate::DynStructError::LayoutOverflow));
+        let capacity = self.capacity();
+        if required > capacity {
+            let doubled = capacity.saturating_mul(2);
+            self.set_capacity(usize::max(4, usize::max(doubled, required)));
+        }
+    }
+
+    /// Resize to `new_len` elements, either by dropping the elements past `new_len`, or by
+    /// appending clones of `value`.
+    pub fn resize(&mut self, new_len: usize, value: D)
+    where
+        D: Clone,
+    {
+        let len = self.len();
+        if new_len > len {
+            self.reserve(new_len - len);
+            for _ in len + 1..new_len {
+                self.push(value.clone());
+            }
+            self.push(value);
+        } else {
+            self.truncate(new_len);
+        }
+    }
+
+    /// Drop all elements after the first `len`. Does nothing if there are fewer than `len`
+    /// elements.
+    pub fn truncate(&mut self, len: usize) {
+        let old_len = self.len();
+        if len < old_len {
+            let tail = core::ptr::slice_from_raw_parts_mut(
+                unsafe { self.many_ptr().add(len) },
+                old_len - len,
+            );
+            // if dropping an element panics, leak the rest rather than dropping them twice
+            self.lengths_mut().len = len;
+            unsafe { core::ptr::drop_in_place(tail) };
+        }
+    }
+
+    /// Shrink the allocation to fit the current elements.
+    pub fn shrink_to_fit(&mut self) {
+        if core::mem::size_of::<D>() != 0 && self.capacity() != self.len() {
+            self.set_capacity(self.len());
+        }
+    }
+
+    /// Convert into a `Box<DynStruct<T, D>>`. At full capacity, and if the value is aligned like
+    /// the lengths in front of it, this reuses the allocation: the value is moved over the
+    /// lengths, and the allocation is shrunk to fit it with `realloc`. Otherwise the value is
+    /// copied into a new allocation, which fits it exactly.
+    pub fn into_box(self) -> Box<DynStruct<T, D>> {
+        let len = self.len();
+        let layout = raw::layout::<DynStruct<T, D>>(len);
+        let (old, offset) = Self::layout(self.capacity());
+        let in_place = len == self.capacity() && old.align() == layout.align();
+
+        // the value is moved out below, so it must not be dropped here
+        let value = self.ptr.as_ptr();
+        core::mem::forget(self);
+        unsafe {
+            let raw = value.sub(offset);
+            let target = if in_place {
+                raw.copy_from(value, layout.size());
+                realloc(raw, old, layout)
+            } else {
+                let target = Allocation::new(layout);
+                target.raw.copy_from_nonoverlapping(value, layout.size());
+                drop(Allocation { raw, layout: old });
+                target.into_raw()
+            };
+
+            Box::from_raw(raw::fat_ptr(target, len))
+        }
+    }
+
+    /// There is room for any number of zero-sized elements.
+    fn fit_capacity(capacity: usize) -> usize {
+        if core::mem::size_of::<D>() == 0 {
+            usize::MAX
+        } else {
+            capacity
+        }
+    }
+
+    /// The layout of an allocation with room for `capacity` elements, and the offset of the value
+    /// in it.
+    fn layout(capacity: usize) -> (Layout, usize) {
+        thin::layout_with_prefix::<Lengths>(raw::layout::<DynStruct<T, D>>(capacity))
+    }
+
+    fn lengths(&self) -> &Lengths {
+        unsafe { &*(self.ptr.as_ptr() as *const Lengths).sub(1) }
+    }
+
+    fn lengths_mut(&mut self) -> &mut Lengths {
+        unsafe { &mut *(self.ptr.as_ptr() as *mut Lengths).sub(1) }
+    }
+
+    fn many_ptr(&self) -> *mut D {
+        raw::many_ptr::<DynStruct<T, D>>(self.ptr.as_ptr())
+    }
+
+    /// Move the allocation to one with room for `capacity` elements.
+    fn set_capacity(&mut self, capacity: usize) {
+        debug_assert!(capacity >= self.len());
+
+        let (old, offset) = Self::layout(self.capacity());
+        let (new, _) = Self::layout(capacity);
+        unsafe {
+            let raw = realloc(self.ptr.as_ptr().sub(offset), old, new);
+            self.ptr = NonNull::new_unchecked(raw.add(offset));
+        }
+        self.lengths_mut().capacity = capacity;
+    }
+}
+
+impl<T, D> Deref for DynVec<T, D> {
+    type Target = DynStruct<T, D>;
+
+    fn deref(&self) -> &DynStruct<T, D> {
+        unsafe { &*raw::fat_ptr(self.ptr.as_ptr(), self.len()) }
+    }
+}
+
+impl<T, D> DerefMut for DynVec<T, D> {
+    fn deref_mut(&mut self) -> &mut DynStruct<T, D> {
+        unsafe { &mut *raw::fat_ptr(self.ptr.as_ptr(), self.len()) }
+    }
+}
+
+impl<T, D> Drop for DynVec<T, D> {
+    fn drop(&mut self) {
+        let (layout, offset) = Self::layout(self.capacity());
+        unsafe {
+            core::ptr::drop_in_place(raw::fat_ptr::<DynStruct<T, D>>(
+                self.ptr.as_ptr(),
+                self.len(),
+            ));
+            drop(Allocation {
+                raw: self.ptr.as_ptr().sub(offset),
+                layout,
+            });
+        }
+    }
+}
+
+impl<T, D> Extend<D> for DynVec<T, D> {
+    fn extend<I: IntoIterator<Item = D>>(&mut self, elements: I) {
+        let elements = elements.into_iter();
+        self.reserve(elements.size_hint().0);
+        elements.for_each(|element| self.push(element));
+    }
+}
+
+impl<T, D> From<DynVec<T, D>> for Box<DynStruct<T, D>> {
+    fn from(vec: DynVec<T, D>) -> Self {
+        vec.into_box()
+    }
+}
+
+impl<T, D> From<Box<DynStruct<T, D>>> for DynVec<T, D> {
+    fn from(boxed: Box<DynStruct<T, D>>) -> Self {
+        DynVec::from_box(boxed)
+    }
+}
+
+impl<T: fmt::Debug, D: fmt::Debug> fmt::Debug for DynVec<T, D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        DynStruct::fmt(self, f)
+    }
+}
+
+/// The buffer behind `DynStruct::resize` and `DynStruct::from_iter`, for any `RawDst`: a value of
+/// `S` with room for `capacity` elements, of which the first `len` are initialized.
+pub(crate) struct RawVec<S: ?Sized + RawDst> {
+    raw: NonNull<u8>,
+    len: usize,
+    capacity: usize,
+    /// Whether the header is initialized. This is only not the case for a buffer created by
+    /// `without_header`, until `write_header` is called.
+    has_header: bool,
+    _marker: PhantomData<Box<S>>,
+}
+
+impl<S: ?Sized + RawDst> RawVec<S> {
+    /// Allocate room for `capacity` elements. The header is only written later by
+    /// `write_header`, which lets it depend on the elements.
+    pub(crate) fn without_header(capacity: usize) -> Self {
+        // there is room for any number of zero-sized elements
+        let capacity = if core::mem::size_of::<S::Element>() == 0 {
+            usize::MAX
+        } else {
+            capacity
+        };
+
+        let raw = Allocation::new(raw::layout::<S>(capacity)).into_raw();
+        RawVec {
+            raw: unsafe { NonNull::new_unchecked(raw) },
+            len: 0,
+            capacity,
+            has_header: false,
+            _marker: PhantomData,
+        }
+    }
+
+    pub(crate) fn from_box(boxed: Box<S>) -> Self {
+        let len = S::ptr_len(&*boxed);
+        let capacity = if core::mem::size_of::<S::Element>() == 0 {
+            usize::MAX
+        } else {
+            len
+        };
+
+        let raw = Box::into_raw(boxed) as *mut u8;
+        RawVec {
+            raw: unsafe { NonNull::new_unchecked(raw) },
+            len,
+            capacity,
+            has_header: true,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Write the header returned by `header` for the current number of elements.
+    pub(crate) fn write_header(&mut self, header: impl FnOnce(usize) -> S::Header) {
+        debug_assert!(!self.has_header);
+
+        let header = header(self.len);
+        unsafe { raw::write_header::<S>(self.raw.as_ptr(), header) };
+        self.has_header = true;
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut S {
+        raw::fat_ptr(self.raw.as_ptr(), self.len)
+    }
+
+    pub(crate) fn push(&mut self, element: S::Element) {
+        if self.len == self.capacity {
+            self.reserve(1);
+        }
+
+        unsafe {
+            raw::many_ptr::<S>(self.raw.as_ptr())
+                .add(self.len)
+                .write(element)
+        };
+        self.len += 1;
+    }
+
+    pub(crate) fn extend<I: IntoIterator<Item = S::Element>>(&mut self, elements: I) {
+        let elements = elements.into_iter();
+        self.reserve(elements.size_hint().0);
+        elements.for_each(|element| self.push(element));
+    }
+
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        let required = self
+            .len
+            .checked_add(additional)
+            .unwrap_or_else(|| panic!("{}", crate::DynStructError::LayoutOverflow));
+        if required > self.capacity {
+            let doubled = self.capacity.saturating_mul(2);
+            self.set_capacity(usize::max(4, usize::max(doubled, required)));
+        }
+    }
+
+    /// Make room for exactly `additional` more elements, unlike `reserve`. Used when the final
+    /// number of elements is known, to grow the allocation only once.
+    pub(crate) fn reserve_exact(&mut self, additional: usize) {
+        let required = self
+            .len
+            .checked_add(additional)
+            .unwrap_or_else(|| panic!("{}", crate::DynStructError::LayoutOverflow));
+        if required > self.capacity {
+            self.set_capacity(required);
+        }
+    }
+
+    pub(crate) fn resize(&mut self, new_len: usize, value: S::Element)
+    where
+        S::Element: Clone,
+    {
+        if new_len > self.len {
+            self.reserve_exact(new_len - self.len);
+            for _ in self.len + 1..new_len {
+                self.push(value.clone());
+            }
+            self.push(value);
+        } else {
+            self.truncate(new_len);
+        }
+    }
+
+    pub(crate) fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            let tail = core::ptr::slice_from_raw_parts_mut(
+                unsafe { raw::many_ptr::<S>(self.raw.as_ptr()).add(len) },
+                self.len - len,
+            );
+            // if dropping an element panics, leak the rest rather than dropping them twice
+            self.len = len;
+            unsafe { core::ptr::drop_in_place(tail) };
+        }
+    }
+
+    pub(crate) fn shrink_to_fit(&mut self) {
+        if core::mem::size_of::<S::Element>() != 0 && self.capacity != self.len {
+            self.set_capacity(self.len);
+        }
+    }
+
+    pub(crate) fn into_box(mut self) -> Box<S> {
+        debug_assert!(self.has_header);
+        self.shrink_to_fit();
+
+        let ptr = self.as_ptr();
+        core::mem::forget(self);
+        unsafe { Box::from_raw(ptr) }
+    }
+
+    /// Move the allocation to one with room for `capacity` elements.
+    fn set_capacity(&mut self, capacity: usize) {
+        debug_assert!(capacity >= self.len);
+
+        let old = raw::layout::<S>(self.capacity);
+        let new = raw::layout::<S>(capacity);
+        unsafe { self.raw = NonNull::new_unchecked(realloc(self.raw.as_ptr(), old, new)) };
+        self.capacity = capacity;
+    }
+}
+
+impl<S: ?Sized + RawDst> Drop for RawVec<S> {
+    fn drop(&mut self) {
+        unsafe {
+            if self.has_header {
+                core::ptr::drop_in_place(self.raw.as_ptr() as *mut S::Header);
+            }
+            core::ptr::drop_in_place(core::ptr::slice_from_raw_parts_mut(
+                raw::many_ptr::<S>(self.raw.as_ptr()),
+                self.len,
+            ));
+            drop(Allocation {
+                raw: self.raw.as_ptr(),
+                layout: raw::layout::<S>(self.capacity),
+            });
+        }
+    }
+}
+
+/// Moves the memory at `raw` from the layout `old` to the layout `new`, which must have the same
+/// alignment. Unlike `alloc::alloc::realloc` this also handles zero-sized layouts.
+///
+/// # Safety
+///
+/// `raw` must be a pointer returned by `Allocation::into_raw` for the layout `old`.
+pub(crate) unsafe fn realloc(raw: *mut u8, old: Layout, new: Layout) -> *mut u8 {
+    debug_assert_eq!(old.align(), new.align());
+
+    if old.size() == new.size() {
+        raw
+    } else if old.size() == 0 {
+        Allocation::new(new).into_raw()
+    } else if new.size() == 0 {
+        alloc::alloc::dealloc(raw, old);
+        Allocation::new(new).into_raw()
+    } else {
+        let raw = alloc::alloc::realloc(raw, old, new.size());
+        if raw.is_null() {
+            alloc::alloc::handle_alloc_error(new)
+        }
+        raw
+    }
+}
@@ -0,0 +1,58 @@
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::num::{
+    NonZeroI128, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize, NonZeroU128,
+    NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU8, NonZeroUsize, Wrapping,
+};
+use core::ptr::NonNull;
+
+/// Types for which a value consisting of only zero bytes is valid.
+///
+/// This is required by [`DynStruct::new_zeroed`](crate::DynStruct::new_zeroed), which creates
+/// values from zero-initialized memory.
+///
+/// # Safety
+///
+/// The all-zero bit pattern has to be a valid value of the type. This is the case for integers
+/// and floats, but not for references, `Box` or `NonZeroU32`, for example.
+pub unsafe trait Zeroable {}
+
+macro_rules! impl_zeroable {
+    ($($ty:ty),* $(,)?) => {
+        $( unsafe impl Zeroable for $ty {} )*
+    };
+}
+
+impl_zeroable! {
+    (), bool, char, f32, f64,
+    u8, u16, u32, u64, u128, usize,
+    i8, i16, i32, i64, i128, isize,
+    Option<NonZeroU8>, Option<NonZeroU16>, Option<NonZeroU32>,
+    Option<NonZeroU64>, Option<NonZeroU128>, Option<NonZeroUsize>,
+    Option<NonZeroI8>, Option<NonZeroI16>, Option<NonZeroI32>,
+    Option<NonZeroI64>, Option<NonZeroI128>, Option<NonZeroIsize>,
+}
+
+unsafe impl<T: ?Sized> Zeroable for PhantomData<T> {}
+unsafe impl<T> Zeroable for MaybeUninit<T> {}
+unsafe impl<T: Zeroable> Zeroable for Wrapping<T> {}
+unsafe impl<T: Zeroable, const N: usize> Zeroable for [T; N] {}
+
+unsafe impl<T> Zeroable for *const T {}
+unsafe impl<T> Zeroable for *mut T {}
+unsafe impl<T> Zeroable for Option<NonNull<T>> {}
+unsafe impl<T> Zeroable for Option<&T> {}
+unsafe impl<T> Zeroable for Option<&mut T> {}
+
+macro_rules! impl_zeroable_tuple {
+    ($($name:ident),*) => {
+        unsafe impl<$($name: Zeroable),*> Zeroable for ($($name,)*) {}
+    };
+}
+
+impl_zeroable_tuple!(A);
+impl_zeroable_tuple!(A, B);
+impl_zeroable_tuple!(A, B, C);
+impl_zeroable_tuple!(A, B, C, D);
+impl_zeroable_tuple!(A, B, C, D, E);
+impl_zeroable_tuple!(A, B, C, D, E, F);
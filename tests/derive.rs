@@ -17,6 +17,507 @@ fn custom() {
     assert_eq!(&foo.values, values);
 }
 
+#[test]
+fn reference_counted() {
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    struct Foo {
+        pub inner: u8,
+        pub values: [u64],
+    }
+
+    let rc: std::rc::Rc<Foo> = Foo::new_rc(3, &[1, 2]);
+    assert_eq!(rc.inner, 3);
+    assert_eq!(&rc.values, &[1, 2]);
+
+    let arc: std::sync::Arc<Foo> = Foo::new_arc(4, &[5]);
+    let clone = std::sync::Arc::clone(&arc);
+    assert_eq!(clone.inner, 4);
+    assert_eq!(&clone.values, &[5]);
+}
+
+#[test]
+fn padding() {
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    struct Foo {
+        pub id: u64,
+        pub flag: bool,
+        pub values: [u16],
+    }
+
+    let foo = Foo::new(1, true, &[7, 8, 9]);
+    assert_eq!(foo.id, 1);
+    assert!(foo.flag);
+    assert_eq!(&foo.values, &[7, 8, 9]);
+    assert_eq!(std::mem::size_of_val(&*foo), 16);
+
+    let rc = Foo::new_rc(2, false, &[10]);
+    assert_eq!(&rc.values, &[10]);
+    assert_eq!(std::mem::size_of_val(&*rc), 16);
+}
+
+#[test]
+fn non_copy() {
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    struct Foo {
+        pub name: String,
+        pub id: u32,
+        pub values: [Vec<u8>],
+    }
+
+    let foo = Foo::new(String::from("foo"), 1, &[vec![1], vec![2, 3]]);
+    assert_eq!(foo.name, "foo");
+    assert_eq!(foo.id, 1);
+    assert_eq!(&foo.values, &[vec![1], vec![2, 3]]);
+
+    let bar = Foo::new_from_vec(String::from("bar"), 2, vec![vec![4]]);
+    assert_eq!(bar.name, "bar");
+    assert_eq!(&bar.values, &[vec![4]]);
+}
+
+#[test]
+fn non_clone() {
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    struct Foo {
+        pub name: String,
+        pub values: [std::sync::Mutex<u32>],
+    }
+
+    let foo = Foo::new_from_vec(String::from("foo"), vec![std::sync::Mutex::new(1)]);
+    assert_eq!(foo.name, "foo");
+    assert_eq!(*foo.values[0].lock().unwrap(), 1);
+}
+
+#[test]
+fn from_iter() {
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    struct Foo {
+        pub name: String,
+        pub values: [u32],
+    }
+
+    let exact = Foo::new_from_iter_exact(String::from("exact"), (0..4).map(|i| i * i));
+    assert_eq!(exact.name, "exact");
+    assert_eq!(&exact.values, &[0, 1, 4, 9]);
+
+    let filtered = Foo::new_from_iter(String::from("filtered"), (0..10).filter(|i| i % 3 == 0));
+    assert_eq!(filtered.name, "filtered");
+    assert_eq!(&filtered.values, &[0, 3, 6, 9]);
+}
+
+#[test]
+fn try_new() {
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    struct Foo {
+        pub inner: u8,
+        pub values: [u16],
+    }
+
+    let foo = Foo::try_new(1, &[2, 3]).unwrap();
+    assert_eq!(foo.inner, 1);
+    assert_eq!(&foo.values, &[2, 3]);
+}
+
+#[cfg(feature = "allocator-api2")]
+#[test]
+fn new_in() {
+    use dyn_struct::allocator_api2::alloc::Global;
+
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    struct Foo {
+        pub inner: u8,
+        pub values: [u16],
+    }
+
+    let foo = Foo::new_in(1, &[2, 3], Global);
+    assert_eq!(foo.inner, 1);
+    assert_eq!(&foo.values, &[2, 3]);
+
+    // header fields may have the same names as the other parameters
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    struct Bar {
+        pub alloc: u8,
+        pub values: [u16],
+    }
+
+    let bar = Bar::new_in(1, &[2, 3], Global);
+    assert_eq!(bar.alloc, 1);
+    assert_eq!(&bar.values, &[2, 3]);
+}
+
+#[test]
+fn new_zeroed() {
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    struct Histogram {
+        pub total: u64,
+        pub scale: f32,
+        pub buckets: [u32],
+    }
+
+    let zeroed = Histogram::new_zeroed(16);
+    assert_eq!(zeroed.total, 0);
+    assert_eq!(zeroed.scale, 0.0);
+    assert_eq!(&zeroed.buckets, &[0; 16]);
+
+    let header = Histogram::new_zeroed_with_header(5, 1.5, 3);
+    assert_eq!(header.total, 5);
+    assert_eq!(header.scale, 1.5);
+    assert_eq!(&header.buckets, &[0; 3]);
+
+    // header fields may have the same names as the other parameters
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    struct Buffer {
+        pub len: u64,
+        pub bytes: [u8],
+    }
+
+    let buffer = Buffer::new_zeroed_with_header(2, 3);
+    assert_eq!(buffer.len, 2);
+    assert_eq!(&buffer.bytes, &[0; 3]);
+}
+
+#[test]
+fn thin() {
+    use dyn_struct::ThinBox;
+
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    struct Foo {
+        pub name: String,
+        pub values: [u16],
+    }
+
+    let foo: ThinBox<Foo> = Foo::new_thin(String::from("thin"), &[1, 2, 3]);
+    assert_eq!(foo.name, "thin");
+    assert_eq!(&foo.values, &[1, 2, 3]);
+    assert_eq!(std::mem::size_of_val(&foo), std::mem::size_of::<usize>());
+
+    let boxed = ThinBox::from_box(Foo::new(String::from("boxed"), &[4]));
+    assert_eq!(boxed.name, "boxed");
+    assert_eq!(&boxed.values, &[4]);
+
+    let clone = foo.clone();
+    assert_eq!(clone.name, "thin");
+    assert_eq!(&clone.values, &[1, 2, 3]);
+}
+
+#[test]
+fn thin_arc() {
+    use dyn_struct::ThinArc;
+
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    struct Token {
+        pub kind: u8,
+        pub text: [u8],
+    }
+
+    let token: ThinArc<Token> = Token::new_thin_arc(1, b"ident");
+    let clone = token.clone();
+    assert_eq!(clone.kind, 1);
+    assert_eq!(&clone.text, b"ident");
+    assert_eq!(ThinArc::strong_count(&token), 2);
+    assert_eq!(std::mem::size_of_val(&clone), std::mem::size_of::<usize>());
+}
+
+#[test]
+fn header_len() {
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    struct Packet {
+        pub kind: u16,
+        pub count: u16,
+        #[dyn_struct(len = count)]
+        pub items: [u32],
+    }
+
+    let packet = Packet::new(7, &[1, 2, 3]);
+    assert_eq!(packet.kind, 7);
+    assert_eq!(packet.count, 3);
+    assert_eq!(&packet.items, &[1, 2, 3]);
+
+    let filtered = Packet::new_from_iter(8, (0..10).filter(|i| i % 4 == 0));
+    assert_eq!(filtered.count, 3);
+    assert_eq!(Packet::new_zeroed(5).count, 5);
+    assert!(Packet::try_new(1, &vec![0; 1 << 16]).is_err());
+
+    #[repr(C, align(4))]
+    struct Buffer([u8; 24]);
+
+    let mut buffer = Buffer([0; 24]);
+    buffer.0[..2].copy_from_slice(&9u16.to_ne_bytes());
+    buffer.0[2..4].copy_from_slice(&2u16.to_ne_bytes());
+    buffer.0[4..8].copy_from_slice(&10u32.to_ne_bytes());
+    buffer.0[8..12].copy_from_slice(&11u32.to_ne_bytes());
+    buffer.0[12..16].copy_from_slice(&12u32.to_ne_bytes());
+
+    let (packet, rest) = Packet::from_bytes_prefix(&buffer.0).unwrap();
+    assert_eq!(packet.kind, 9);
+    assert_eq!(&packet.items, &[10, 11]);
+    assert_eq!(rest.len(), 12);
+    assert_eq!(rest[..4], 12u32.to_ne_bytes());
+
+    buffer.0[2..4].copy_from_slice(&6u16.to_ne_bytes());
+    assert!(Packet::from_bytes_prefix(&buffer.0).is_err());
+
+    // the length field may have the same name as the parameters of the constructors
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    #[dyn_struct(clone, default)]
+    struct Bytes {
+        pub len: u8,
+        #[dyn_struct(len = len)]
+        pub bytes: [u8],
+    }
+
+    let bytes = Bytes::new(&[1, 2]);
+    assert_eq!(bytes.len, 2);
+    assert_eq!(bytes.clone().len, 2);
+    assert_eq!(Bytes::new_zeroed(3).len, 3);
+    assert_eq!(Bytes::new_zeroed_with_header(4).len, 4);
+    assert_eq!(Bytes::new_from_iter(0..5).len, 5);
+    assert_eq!(Bytes::new_from_iter_exact(0..6).len, 6);
+    assert_eq!(Box::<Bytes>::default().len, 0);
+}
+
+#[test]
+fn as_bytes() {
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    struct Record {
+        pub id: u32,
+        pub count: u32,
+        #[dyn_struct(len = count)]
+        pub values: [u16],
+    }
+
+    let record = Record::new(1, &[2, 3]);
+    assert_eq!(record.as_bytes().len(), 12);
+    assert_eq!(record.as_bytes()[..4], 1u32.to_ne_bytes());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn write_to() {
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    struct Record {
+        pub id: u32,
+        pub count: u32,
+        #[dyn_struct(len = count)]
+        pub values: [u16],
+    }
+
+    let record = Record::new(1, &[2, 3]);
+    let mut bytes = Vec::new();
+    record.write_to(&mut bytes).unwrap();
+    let read = Record::read_from(&bytes[..]).unwrap();
+    assert_eq!(read.id, 1);
+    assert_eq!(read.count, 2);
+    assert_eq!(&read.values, &[2, 3]);
+
+    // a length which disagrees with the number of elements is rejected
+    bytes[12..16].copy_from_slice(&3u32.to_ne_bytes());
+    assert!(Record::read_from(&bytes[..]).is_err());
+}
+
+#[test]
+fn layout() {
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    struct Foo {
+        pub kind: u8,
+        pub id: u64,
+        pub flag: bool,
+        pub values: [u16],
+    }
+
+    for len in 0..4 {
+        let foo = Foo::new(1, 2, true, &vec![3; len]);
+        let layout = Foo::layout_for(len).unwrap();
+        assert_eq!(layout.size(), std::mem::size_of_val(&*foo));
+        assert_eq!(layout.align(), std::mem::align_of_val(&*foo));
+
+        let start = std::ptr::addr_of!(*foo) as *const u8;
+        let values = std::ptr::addr_of!(foo.values) as *const u8;
+        assert_eq!(values as usize - start as usize, Foo::tail_offset());
+    }
+
+    const TAIL_OFFSET: usize = Foo::tail_offset();
+    assert_eq!(TAIL_OFFSET, 18);
+    assert!(Foo::layout_for(Foo::max_len()).is_ok());
+    assert!(Foo::layout_for(Foo::max_len() + 1).is_err());
+}
+
+#[test]
+fn raw_parts() {
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    struct Foo {
+        pub name: String,
+        pub values: [u8],
+    }
+
+    let foo = Foo::new(String::from("foo"), &[1, 2, 3]);
+    let (data, len) = Foo::into_raw_parts(foo);
+    assert_eq!(len, 3);
+
+    unsafe {
+        Foo::from_raw_parts_mut(data, len).values[2] = 4;
+        assert_eq!(&Foo::from_raw_parts_ref(data, len).values, &[1, 2, 4]);
+        assert_eq!(Foo::ptr_from_raw_parts(data, len) as *mut u8, data);
+
+        let foo = Foo::from_raw_parts(data, len);
+        assert_eq!(foo.name, "foo");
+        assert_eq!(&foo.values, &[1, 2, 4]);
+    }
+}
+
+#[test]
+fn clone() {
+    #[repr(C)]
+    #[derive(Debug, PartialEq, DynStruct)]
+    #[dyn_struct(clone, default, to_owned)]
+    struct Foo {
+        pub name: String,
+        pub count: u16,
+        #[dyn_struct(len = count)]
+        pub values: [Vec<u8>],
+    }
+
+    let foo = Foo::new(String::from("foo"), &[vec![1], vec![2, 3]]);
+    let clone = foo.clone();
+    assert_eq!(clone, foo);
+    assert_eq!(clone.count, 2);
+
+    let default = Box::<Foo>::default();
+    assert_eq!(default.name, "");
+    assert_eq!(default.count, 0);
+    assert!(default.values.is_empty());
+
+    let mut cow = std::borrow::Cow::Borrowed(&*foo);
+    cow.to_mut().values[1].push(4);
+    assert_eq!(&cow.values, &[vec![1], vec![2, 3, 4]]);
+    assert_eq!(&foo.values, &[vec![1], vec![2, 3]]);
+}
+
+#[test]
+fn collection() {
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    #[dyn_struct(collection)]
+    struct Foo {
+        pub name: &'static str,
+        pub values: [u32],
+    }
+
+    let mut foo = Foo::new("foo", &[1, 2, 3]);
+    assert_eq!(foo.len(), 3);
+    assert!(!foo.is_empty());
+    assert_eq!(foo[1], 2);
+    assert_eq!(&foo[1..], &[2, 3]);
+
+    foo[0] = 4;
+    for value in &mut *foo {
+        *value *= 2;
+    }
+    assert_eq!((&*foo).into_iter().sum::<u32>(), 18);
+    assert_eq!(AsRef::<[u32]>::as_ref(&*foo), &[8, 4, 6]);
+    AsMut::<[u32]>::as_mut(&mut *foo).reverse();
+    assert_eq!(&foo.values, &[6, 4, 8]);
+
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    #[dyn_struct(deref)]
+    struct Bar<T> {
+        pub name: &'static str,
+        pub values: [T],
+    }
+
+    let mut bar = Bar::new("bar", &[1u8, 2]);
+    bar.sort_by(|a, b| b.cmp(a));
+    assert_eq!(bar.first(), Some(&2));
+    assert_eq!(bar.len(), 2);
+}
+
+mod reexport {
+    pub use dyn_struct as inner;
+}
+
+#[test]
+fn constructor() {
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    #[dyn_struct(new = create, vis = "pub(crate)", param = values)]
+    #[dyn_struct(crate = "crate::reexport::inner")]
+    struct Foo {
+        pub id: u32,
+        pub values: [u8],
+    }
+
+    impl Foo {
+        fn new(id: u32) -> Box<Foo> {
+            Foo::create(id, &[])
+        }
+    }
+
+    let foo = Foo::create(1, &[2, 3]);
+    assert_eq!(foo.id, 1);
+    assert_eq!(&foo.values, &[2, 3]);
+    assert!(Foo::new(4).values.is_empty());
+
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    #[dyn_struct(skip_new)]
+    struct Bar {
+        pub id: u32,
+        pub values: [u8],
+    }
+
+    impl Bar {
+        fn new(values: &[u8]) -> Box<Bar> {
+            Bar::new_from_vec(values.len() as u32, values.to_vec())
+        }
+    }
+
+    let bar = Bar::new(&[1, 2]);
+    assert_eq!(bar.id, 2);
+    assert_eq!(&bar.values, &[1, 2]);
+}
+
+#[test]
+fn repr_align() {
+    #[repr(align(16), C)]
+    #[derive(Debug, DynStruct)]
+    struct Foo {
+        pub id: u8,
+        pub values: [u16],
+    }
+
+    for len in 0..10 {
+        let foo = Foo::new(1, &vec![2; len]);
+        let layout = Foo::layout_for(len).unwrap();
+        assert_eq!(layout.align(), 16);
+        assert_eq!(layout.align(), std::mem::align_of_val(&*foo));
+        assert_eq!(layout.size(), std::mem::size_of_val(&*foo));
+        assert_eq!(&*foo as *const Foo as *const u8 as usize % 16, 0);
+        assert_eq!(Foo::tail_offset(), 2);
+    }
+
+    let rc = Foo::new_rc(3, &[4; 9]);
+    assert_eq!(std::mem::size_of_val(&*rc), 32);
+    assert_eq!(&*rc as *const Foo as *const u8 as usize % 16, 0);
+}
+
 #[test]
 fn generic() {
     #[repr(C)]
@@ -36,6 +537,71 @@ fn generic() {
     assert_eq!(&foo.values, values);
 }
 
+#[test]
+fn tuple() {
+    #[repr(C)]
+    #[derive(Debug, PartialEq, DynStruct)]
+    #[dyn_struct(clone, collection)]
+    struct Row(pub u32, pub bool, pub [u8]);
+
+    let row = Row::new(1, true, &[2, 3]);
+    assert_eq!(row.0, 1);
+    assert!(row.1);
+    assert_eq!(&row.2, &[2, 3]);
+    assert_eq!(row.clone(), row);
+    assert_eq!(row.len(), 2);
+    assert_eq!(row[1], 3);
+
+    let moved = Row::new_from_iter(4, false, 5..8);
+    assert_eq!(&moved.2, &[5, 6, 7]);
+    assert_eq!(Row::tail_offset(), 5);
+    assert_eq!(std::mem::size_of_val(&*moved), 8);
+
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    struct Generic<T>(T, [T]);
+
+    let generic = Generic::new(String::from("a"), &[String::from("b")]);
+    assert_eq!(generic.0, "a");
+    assert_eq!(&generic.1, &["b"]);
+}
+
+#[test]
+fn generic_defaults() {
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    struct Foo<T = u8, const N: usize = 2>
+    where
+        T: Copy,
+    {
+        pub header: [T; N],
+        pub values: [T],
+    }
+
+    let foo: Box<Foo> = Foo::new([1, 2], &[3]);
+    assert_eq!(foo.header, [1, 2]);
+    assert_eq!(&foo.values, &[3]);
+
+    let wide = Foo::<u64, 3>::new_zeroed(2);
+    assert_eq!(wide.header, [0; 3]);
+    assert_eq!(Foo::<u64, 3>::tail_offset(), 24);
+    assert_eq!(std::mem::size_of_val(&*wide), 40);
+
+    #[repr(C)]
+    #[derive(Debug, DynStruct)]
+    struct Named<'a, T: ?Sized, D>
+    where
+        D: Clone,
+    {
+        pub name: &'a T,
+        pub values: [D],
+    }
+
+    let named = Named::new("name", &[1u16, 2]);
+    assert_eq!(named.name, "name");
+    assert_eq!(&named.values, &[1, 2]);
+}
+
 #[test]
 fn readme() {
     #[repr(C)]